@@ -0,0 +1,113 @@
+//! A typed interface to the [`Resource::TotalMemory`] limit (RLIMIT_AS), the
+//! maximum size of the virtual address space of the process in bytes.
+
+use crate::{
+    get_resource_limit, limit_from_u64, rlim_to_u64, set_soft_limit, GetRLimitError, Resource,
+    ResourceLimit, ResourceLimits, SetRLimitError,
+};
+
+/// The number of bytes in a mebibyte.
+const MIB: libc::rlim_t = 1024 * 1024;
+
+/// The address space limits, as returned by [`get`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct MemoryLimit(pub ResourceLimits);
+
+impl MemoryLimit {
+    /// The soft limit in bytes, or `None` if it is unlimited or unknown.
+    pub fn soft_bytes(&self) -> Option<libc::rlim_t> {
+        self.0.as_options().0
+    }
+
+    /// The hard limit in bytes, or `None` if it is unlimited or unknown.
+    pub fn hard_bytes(&self) -> Option<libc::rlim_t> {
+        self.0.as_options().1
+    }
+
+    /// The soft limit in whole mebibytes, rounded down, or `None` if it is
+    /// unlimited or unknown.
+    pub fn soft_mib(&self) -> Option<libc::rlim_t> {
+        self.soft_bytes().map(|bytes| bytes / MIB)
+    }
+
+    /// The hard limit in whole mebibytes, rounded down, or `None` if it is
+    /// unlimited or unknown.
+    pub fn hard_mib(&self) -> Option<libc::rlim_t> {
+        self.hard_bytes().map(|bytes| bytes / MIB)
+    }
+
+    /// The underlying limit values.
+    pub fn limits(&self) -> ResourceLimits {
+        self.0
+    }
+}
+
+impl From<MemoryLimit> for ResourceLimits {
+    fn from(limit: MemoryLimit) -> Self {
+        limit.0
+    }
+}
+
+/// Get the address space limits of the current process.
+pub fn get() -> Result<MemoryLimit, GetRLimitError> {
+    get_resource_limit(Resource::TotalMemory).map(MemoryLimit)
+}
+
+/// Set the soft address space limit to `n` bytes, keeping the current hard
+/// limit. Returns the new limits.
+pub fn set_soft_bytes(n: libc::rlim_t) -> Result<MemoryLimit, SetRLimitError> {
+    set_soft_limit(Resource::TotalMemory, ResourceLimit::Value(n)).map(MemoryLimit)
+}
+
+/// Set the soft address space limit to `m` mebibytes, keeping the current
+/// hard limit. Returns the new limits. A size too large for a limit value
+/// sets no limit.
+pub fn set_soft_mib(m: libc::rlim_t) -> Result<MemoryLimit, SetRLimitError> {
+    let bytes = limit_from_u64(rlim_to_u64(m).saturating_mul(rlim_to_u64(MIB)));
+    set_soft_limit(Resource::TotalMemory, bytes).map(MemoryLimit)
+}
+
+/// Get the current address space limits with the soft limit replaced by the
+/// given fraction of the total physical memory of the system (see
+/// [`ResourceLimit::from_memory_fraction`]). The limits are not applied.
+pub fn from_system_fraction(fraction: f64) -> std::io::Result<MemoryLimit> {
+    let limits = get_resource_limit(Resource::TotalMemory)?;
+    Ok(MemoryLimit(ResourceLimits {
+        soft_limit: ResourceLimit::from_memory_fraction(fraction)?,
+        hard_limit: limits.hard_limit,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits;
+
+    #[test]
+    fn converts_to_whole_mebibytes() {
+        let limit = MemoryLimit(limits(3 * MIB + 1, 8 * MIB));
+        assert_eq!(
+            (limit.soft_bytes(), limit.hard_bytes()),
+            (Some(3 * MIB + 1), Some(8 * MIB))
+        );
+        assert_eq!((limit.soft_mib(), limit.hard_mib()), (Some(3), Some(8)));
+        let unlimited = MemoryLimit(ResourceLimits::from_options((None, None)));
+        assert_eq!((unlimited.soft_mib(), unlimited.hard_mib()), (None, None));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn set_soft_mib_scales_to_bytes() {
+        let unlimited = ResourceLimits::from_options((None, None));
+        crate::mock::set_table(&[(Resource::TotalMemory, unlimited)]);
+        assert_eq!(
+            set_soft_mib(512).map(|limit| limit.soft_bytes()),
+            Ok(Some(512 * MIB))
+        );
+        // Too large to be a number of bytes, so no limit is set.
+        assert_eq!(
+            set_soft_mib(libc::rlim_t::MAX).map(|limit| limit.limits()),
+            Ok(unlimited)
+        );
+    }
+}
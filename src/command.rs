@@ -0,0 +1,161 @@
+use crate::{to_raw_pair, Resource, ResourceLimits};
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command};
+
+/// Spawn `cmd` with the given limits applied to the child process only. The
+/// limits are set between fork and exec, so the limits of the current
+/// process are never changed. If any limit cannot be set the spawn fails with
+/// the corresponding OS error.
+pub fn spawn_with_limits(
+    cmd: &mut Command,
+    limits: &[(Resource, ResourceLimits)],
+) -> std::io::Result<Child> {
+    let apply_limits = limit_preexec_fn(limits.to_vec());
+    unsafe {
+        cmd.pre_exec(apply_limits);
+    }
+    cmd.spawn()
+}
+
+/// Create a closure which sets the given limits, for passing to
+/// [`CommandExt::pre_exec`] alongside other setup. The closure only calls
+/// setrlimit, which is async-signal-safe, and does not allocate. It fails with
+/// the OS error of the first limit which cannot be set.
+pub fn limit_preexec_fn(
+    limits: Vec<(Resource, ResourceLimits)>,
+) -> impl FnMut() -> std::io::Result<()> + Send + Sync + 'static {
+    // Convert everything up front: only async-signal-safe work may be done in
+    // the child after fork, which rules out allocating.
+    let raw_limits: Vec<(libc::__rlimit_resource_t, libc::rlimit)> = limits
+        .into_iter()
+        .map(|(resource, limits)| to_raw_pair(resource, limits))
+        .collect();
+    move || {
+        for (resource, rlimit) in &raw_limits {
+            if unsafe { libc::setrlimit(*resource, rlimit) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fork the current process, set the given limits in the child, then run
+/// `child_fn` in the child and exit with the status it returns. The pid of the
+/// child is returned to the parent, whose limits are left unchanged; the
+/// parent is responsible for waiting on the child.
+///
+/// The limits are converted before forking and the child only calls
+/// setrlimit and `_exit`, both async-signal-safe. If the parent has more than
+/// one thread, `child_fn` is subject to the same restrictions: it must only
+/// call async-signal-safe functions, which rules out allocating, taking locks,
+/// and most of the standard library. If a limit cannot be set the child exits
+/// with status 127 without running `child_fn`.
+///
+/// If `child_fn` panics the child also exits with status 127, so that the
+/// panic never unwinds into the caller's code running in the child. The panic
+/// hook still runs first, and since it allocates and prints it is not
+/// async-signal-safe either; avoid panicking in the child.
+pub fn fork_with_limits<F: FnOnce() -> libc::c_int>(
+    limits: &[(Resource, ResourceLimits)],
+    child_fn: F,
+) -> std::io::Result<libc::pid_t> {
+    let raw_limits: Vec<(libc::__rlimit_resource_t, libc::rlimit)> = limits
+        .iter()
+        .map(|&(resource, limits)| to_raw_pair(resource, limits))
+        .collect();
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error()),
+        0 => {
+            for (resource, rlimit) in &raw_limits {
+                if unsafe { libc::setrlimit(*resource, rlimit) } != 0 {
+                    unsafe { libc::_exit(127) };
+                }
+            }
+            let status = std::panic::catch_unwind(std::panic::AssertUnwindSafe(child_fn));
+            unsafe { libc::_exit(status.unwrap_or(127)) }
+        }
+        pid => Ok(pid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The real open files limits of the current process, which are what
+    /// children start with even when the mock is enabled.
+    fn real_open_files() -> ResourceLimits {
+        let mut rlimit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        assert_eq!(
+            unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlimit) },
+            0
+        );
+        rlimit.into()
+    }
+
+    /// The real open files limits with the soft limit lowered to 64.
+    fn lowered_open_files() -> ResourceLimits {
+        ResourceLimits {
+            soft_limit: crate::ResourceLimit::Value(64),
+            ..real_open_files()
+        }
+    }
+
+    #[test]
+    fn spawning_leaves_the_parent_unchanged() {
+        let before = real_open_files();
+        let limits = [(Resource::OpenFiles, lowered_open_files())];
+        let status = spawn_with_limits(&mut Command::new("true"), &limits)
+            .unwrap()
+            .wait()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(real_open_files(), before);
+    }
+
+    #[test]
+    fn preexec_fn_applies_to_the_child() {
+        let apply_limits = limit_preexec_fn(vec![(Resource::OpenFiles, lowered_open_files())]);
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "ulimit -n"]);
+        unsafe {
+            cmd.pre_exec(apply_limits);
+        }
+        let output = cmd.output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "64");
+    }
+
+    #[test]
+    fn forked_child_sees_the_limits() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let [read_fd, write_fd] = fds;
+        let limits = [(Resource::OpenFiles, lowered_open_files())];
+        // Only async-signal-safe calls are made in the child.
+        let pid = fork_with_limits(&limits, || {
+            let mut rlimit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlimit) };
+            let bytes = rlimit.rlim_cur.to_ne_bytes();
+            let written = unsafe { libc::write(write_fd, bytes.as_ptr().cast(), bytes.len()) };
+            libc::c_int::from(written != bytes.len() as isize)
+        })
+        .unwrap();
+        unsafe { libc::close(write_fd) };
+        let mut bytes = [0; std::mem::size_of::<libc::rlim_t>()];
+        let read = unsafe { libc::read(read_fd, bytes.as_mut_ptr().cast(), bytes.len()) };
+        unsafe { libc::close(read_fd) };
+        let mut status = 0;
+        assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+        assert!(libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0);
+        assert_eq!(read, bytes.len() as isize);
+        assert_eq!(libc::rlim_t::from_ne_bytes(bytes), 64);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{
+    set_soft_limit, ParseResourceLimitError, Resource, ResourceLimit, ResourceLimits,
+    SetRLimitError,
+};
+
+/// The environment variable used for the soft limit of a resource, e.g.
+/// `RLIMIT_NOFILE`.
+fn env_var_name(resource: Resource) -> String {
+    format!("RLIMIT_{}", resource.to_string().to_ascii_uppercase())
+}
+
+/// An error value returned from the failure of [`apply_limits_from_env`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EnvLimitError {
+    /// The value of the variable for a resource is not a valid limit.
+    Parse(Resource, ParseResourceLimitError),
+    /// The soft limit of a resource could not be set.
+    Set(Resource, SetRLimitError),
+}
+
+impl std::fmt::Display for EnvLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Parse(resource, err) => write!(f, "{}: {}", env_var_name(*resource), err),
+            Self::Set(resource, err) => {
+                write!(f, "could not set {} limit: {}", resource, err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EnvLimitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Parse(_, err) => Some(err),
+            Self::Set(_, err) => Some(err),
+        }
+    }
+}
+
+/// Render the soft limits of the given resources as environment variable
+/// assignments, e.g. `("RLIMIT_NOFILE", "1024")`, which can be read back by
+/// [`limits_from_env`] and [`apply_limits_from_env`].
+pub fn to_env_vars(limits: &[(Resource, ResourceLimits)]) -> Vec<(String, String)> {
+    limits
+        .iter()
+        .map(|(resource, limits)| (env_var_name(*resource), limits.soft_limit.to_string()))
+        .collect()
+}
+
+/// Read soft limits from `RLIMIT_<NAME>` environment variables (such as
+/// `RLIMIT_NOFILE`), in the canonical resource order. Resources without a
+/// variable set are omitted.
+pub fn limits_from_env() -> Result<Vec<(Resource, ResourceLimit)>, EnvLimitError> {
+    Resource::all()
+        .iter()
+        .filter_map(|&resource| {
+            let value = std::env::var(env_var_name(resource)).ok()?;
+            Some(
+                value
+                    .parse()
+                    .map(|limit| (resource, limit))
+                    .map_err(|err| EnvLimitError::Parse(resource, err)),
+            )
+        })
+        .collect()
+}
+
+/// Set the soft limits given by `RLIMIT_<NAME>` environment variables (see
+/// [`limits_from_env`]), leaving the hard limits unchanged. Returns the new
+/// limits of each resource that was set. All variables are parsed before any
+/// limit is set; setting stops at the first failure.
+pub fn apply_limits_from_env() -> Result<Vec<(Resource, ResourceLimits)>, EnvLimitError> {
+    limits_from_env()?
+        .into_iter()
+        .map(|(resource, soft_limit)| {
+            set_soft_limit(resource, soft_limit)
+                .map(|limits| (resource, limits))
+                .map_err(|err| EnvLimitError::Set(resource, err))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_vars_round_trip() {
+        let limits = [
+            (
+                Resource::OpenFiles,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Value(1024),
+                    hard_limit: ResourceLimit::Value(4096),
+                },
+            ),
+            (
+                Resource::StackSize,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Infinity,
+                    hard_limit: ResourceLimit::Infinity,
+                },
+            ),
+        ];
+        let vars = to_env_vars(&limits);
+        assert_eq!(
+            vars,
+            vec![
+                ("RLIMIT_NOFILE".to_string(), "1024".to_string()),
+                ("RLIMIT_STACK".to_string(), "unlimited".to_string()),
+            ]
+        );
+        for (name, value) in &vars {
+            std::env::set_var(name, value);
+        }
+        let read = limits_from_env();
+        for (name, _) in &vars {
+            std::env::remove_var(name);
+        }
+        assert_eq!(
+            read,
+            Ok(vec![
+                (Resource::OpenFiles, ResourceLimit::Value(1024)),
+                (Resource::StackSize, ResourceLimit::Infinity),
+            ])
+        );
+    }
+}
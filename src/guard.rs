@@ -0,0 +1,258 @@
+use crate::{
+    get_resource_limit, raise_soft_to_hard, set_resource_limit, set_soft_limit, Resource,
+    ResourceLimit, ResourceLimits, SetRLimitError,
+};
+
+/// Restores the previous limit values of a resource when dropped. Created by
+/// [`scoped_set_resource_limit`].
+///
+/// Restoring can fail, for example if the hard limit was lowered and the
+/// process is not privileged enough to raise it again. Such failures are
+/// ignored when the guard is dropped.
+#[must_use = "the previous limits are restored as soon as the guard is dropped"]
+#[derive(Debug)]
+pub struct ResourceLimitGuard {
+    resource: Resource,
+    previous: ResourceLimits,
+}
+
+impl ResourceLimitGuard {
+    /// The resource whose limits will be restored.
+    pub fn resource(&self) -> Resource {
+        self.resource
+    }
+
+    /// The limit values which will be restored.
+    pub fn previous(&self) -> ResourceLimits {
+        self.previous
+    }
+}
+
+impl Drop for ResourceLimitGuard {
+    fn drop(&mut self) {
+        let _ = set_resource_limit(self.resource, self.previous);
+    }
+}
+
+/// Set the limit values for a particular resource until the returned guard is
+/// dropped, at which point the previous values are restored.
+///
+/// If setting the new limits fails no guard is created, so nothing will be
+/// restored.
+pub fn scoped_set_resource_limit(
+    resource: Resource,
+    limits: ResourceLimits,
+) -> Result<ResourceLimitGuard, SetRLimitError> {
+    let previous = get_resource_limit(resource)?;
+    set_resource_limit(resource, limits)?;
+    Ok(ResourceLimitGuard { resource, previous })
+}
+
+/// Run `f` with core dumps disabled, by setting the soft
+/// [`Resource::CoreFileSize`] limit to 0. The previous limit is restored
+/// afterwards, including when `f` panics. If core dumps cannot be disabled `f`
+/// is not run.
+pub fn without_core_dumps<F, R>(f: F) -> Result<R, SetRLimitError>
+where
+    F: FnOnce() -> R,
+{
+    let limits = get_resource_limit(Resource::CoreFileSize)?;
+    let _guard = scoped_set_resource_limit(
+        Resource::CoreFileSize,
+        ResourceLimits {
+            soft_limit: ResourceLimit::Value(0),
+            hard_limit: limits.hard_limit,
+        },
+    )?;
+    Ok(f())
+}
+
+/// Raises the soft [`Resource::OpenFiles`] limit to the hard limit, for
+/// example for the startup phase of a program which briefly needs many file
+/// descriptors, and restores the original soft limit when dropped. Created by
+/// [`NoFileBoost::maximize`].
+///
+/// Only the soft limit is restored, so if the hard limit was lowered in the
+/// meantime restoring may fail. Such failures are ignored.
+#[must_use = "the soft limit is restored as soon as the boost is dropped"]
+#[derive(Debug)]
+pub struct NoFileBoost {
+    original_soft: ResourceLimit,
+}
+
+impl NoFileBoost {
+    /// Raise the soft open files limit to the hard limit until the returned
+    /// value is dropped.
+    pub fn maximize() -> Result<NoFileBoost, SetRLimitError> {
+        let original_soft = get_resource_limit(Resource::OpenFiles)?.soft_limit;
+        raise_soft_to_hard(Resource::OpenFiles)?;
+        Ok(NoFileBoost { original_soft })
+    }
+
+    /// The soft limit which will be restored.
+    pub fn original_soft(&self) -> ResourceLimit {
+        self.original_soft
+    }
+}
+
+impl Drop for NoFileBoost {
+    fn drop(&mut self) {
+        let _ = set_soft_limit(Resource::OpenFiles, self.original_soft);
+    }
+}
+
+/// Run `f` with the soft limit of a particular resource raised to `target`,
+/// raising the hard limit too if it is lower. A soft limit which is already at
+/// least `target` is left as it is. If the limit cannot be raised
+/// (usually because the process is not privileged enough to raise the hard
+/// limit), `f` is run with nothing changed. `f` is passed whether the limit was
+/// raised, and the previous limits are restored afterwards, including when `f`
+/// panics.
+pub fn try_with_raised_limit<F, R>(resource: Resource, target: libc::rlim_t, f: F) -> R
+where
+    F: FnOnce(bool) -> R,
+{
+    let target = ResourceLimit::Value(target);
+    let guard = get_resource_limit(resource)
+        .map_err(SetRLimitError::from)
+        .and_then(|limits| {
+            if !target.exceeds(&limits.soft_limit) {
+                return Ok(None);
+            }
+            let hard_limit = if target.exceeds(&limits.hard_limit) {
+                target
+            } else {
+                limits.hard_limit
+            };
+            scoped_set_resource_limit(
+                resource,
+                ResourceLimits {
+                    soft_limit: target,
+                    hard_limit,
+                },
+            )
+            .map(Some)
+        });
+    match guard {
+        Ok(_guard) => f(true),
+        Err(_) => f(false),
+    }
+}
+
+/// The soft open files limit used by [`probe_open_files_limit`].
+const PROBE_OPEN_FILES: libc::rlim_t = 64;
+
+/// Check that the kernel enforces the open files limit: temporarily lower the
+/// soft [`Resource::OpenFiles`] limit to 64 (or less if it is already
+/// lower), open files until that fails with EMFILE, and return how many were
+/// opened. The files are closed and the previous limit restored before
+/// returning, including on error.
+///
+/// The limit applies to descriptor numbers, so descriptors already open below
+/// the limit reduce the count.
+pub fn probe_open_files_limit() -> std::io::Result<u64> {
+    let limits = get_resource_limit(Resource::OpenFiles)?;
+    let soft_limit = match limits.soft_limit {
+        ResourceLimit::Value(n) => n.min(PROBE_OPEN_FILES),
+        ResourceLimit::Infinity | ResourceLimit::Unknown => PROBE_OPEN_FILES,
+    };
+    let _guard = scoped_set_resource_limit(
+        Resource::OpenFiles,
+        ResourceLimits {
+            soft_limit: ResourceLimit::Value(soft_limit),
+            hard_limit: limits.hard_limit,
+        },
+    )?;
+    let mut files = Vec::new();
+    loop {
+        match std::fs::File::open("/dev/null") {
+            Ok(file) => files.push(file),
+            Err(err) if err.raw_os_error() == Some(libc::EMFILE) => break,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(files.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "mock")]
+    use crate::{limits, mock};
+
+    #[cfg(all(not(feature = "mock"), target_os = "linux"))]
+    #[test]
+    fn probe_opens_files_up_to_the_limit() {
+        // The probe lowers the limit of the whole process, which would make
+        // other tests fail to open files.
+        if !crate::in_isolated_process("guard::tests::probe_opens_files_up_to_the_limit") {
+            return;
+        }
+        let open = crate::open_file_count().unwrap();
+        let count = probe_open_files_limit().unwrap();
+        let limit = crate::rlim_to_u64(PROBE_OPEN_FILES);
+        assert!(count <= limit - 3);
+        assert!(count + open >= limit);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn rejected_set_creates_no_guard() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        assert_eq!(
+            scoped_set_resource_limit(Resource::OpenFiles, limits(1024, 8192)).err(),
+            Some(SetRLimitError::Permission)
+        );
+        assert_eq!(mock::table(), [(Resource::OpenFiles, limits(1024, 4096))]);
+        let guard = scoped_set_resource_limit(Resource::OpenFiles, limits(512, 4096)).unwrap();
+        assert_eq!(guard.previous(), limits(1024, 4096));
+        drop(guard);
+        assert_eq!(mock::table(), [(Resource::OpenFiles, limits(1024, 4096))]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn core_dumps_are_disabled_only_inside() {
+        let initial = ResourceLimits::from_options((Some(1 << 20), None));
+        mock::set_table(&[(Resource::CoreFileSize, initial)]);
+        let inside = without_core_dumps(|| get_resource_limit(Resource::CoreFileSize));
+        assert_eq!(
+            inside,
+            Ok(Ok(ResourceLimits::from_options((Some(0), None))))
+        );
+        assert_eq!(mock::table(), [(Resource::CoreFileSize, initial)]);
+        let panicked = std::panic::catch_unwind(|| without_core_dumps(|| panic!("inside")));
+        assert!(panicked.is_err());
+        assert_eq!(mock::table(), [(Resource::CoreFileSize, initial)]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn boost_is_undone_on_drop() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        let boost = NoFileBoost::maximize().unwrap();
+        assert_eq!(boost.original_soft(), ResourceLimit::Value(1024));
+        assert_eq!(mock::table(), [(Resource::OpenFiles, limits(4096, 4096))]);
+        drop(boost);
+        assert_eq!(mock::table(), [(Resource::OpenFiles, limits(1024, 4096))]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn runs_unraised_without_privileges() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        let inside =
+            try_with_raised_limit(Resource::OpenFiles, 8192, |raised| (raised, mock::table()));
+        assert_eq!(
+            inside,
+            (false, vec![(Resource::OpenFiles, limits(1024, 4096))])
+        );
+        let inside =
+            try_with_raised_limit(Resource::OpenFiles, 2048, |raised| (raised, mock::table()));
+        assert_eq!(
+            inside,
+            (true, vec![(Resource::OpenFiles, limits(2048, 4096))])
+        );
+        assert_eq!(mock::table(), [(Resource::OpenFiles, limits(1024, 4096))]);
+    }
+}
@@ -0,0 +1,74 @@
+//! Gauges recorded with the `metrics` crate when the `metrics` feature is
+//! enabled. Without the feature these functions do nothing.
+//!
+//! Soft limits are recorded as `rlimit_soft` and usage as `rlimit_used`, each
+//! labelled with the resource name. Unlimited soft limits are recorded as
+//! positive infinity, and unknown ones are not recorded.
+
+use crate::{Resource, ResourceLimit};
+
+/// Record the soft limit of a resource.
+pub(crate) fn record_soft_limit(resource: Resource, soft_limit: ResourceLimit) {
+    #[cfg(feature = "metrics")]
+    {
+        let value = match soft_limit {
+            ResourceLimit::Value(n) => n as f64,
+            ResourceLimit::Infinity => f64::INFINITY,
+            ResourceLimit::Unknown => return,
+        };
+        metrics::gauge!("rlimit_soft", "resource" => resource.to_string()).set(value);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (resource, soft_limit);
+}
+
+/// Record the current usage of a resource.
+pub(crate) fn record_usage(resource: Resource, used: u64) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!("rlimit_used", "resource" => resource.to_string()).set(used as f64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (resource, used);
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::MetricKind;
+
+    #[test]
+    fn records_gauges() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        metrics::with_local_recorder(&recorder, || {
+            record_soft_limit(Resource::OpenFiles, ResourceLimit::Value(1024));
+            record_soft_limit(Resource::CPUTime, ResourceLimit::Infinity);
+            record_soft_limit(Resource::StackSize, ResourceLimit::Unknown);
+            record_usage(Resource::TotalMemory, 4096);
+        });
+        let mut gauges: Vec<(String, String, f64)> = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                assert_eq!(key.kind(), MetricKind::Gauge);
+                let key = key.key();
+                let label = key.labels().next().expect("resource label");
+                assert_eq!(label.key(), "resource");
+                let DebugValue::Gauge(value) = value else {
+                    panic!("not a gauge");
+                };
+                (key.name().to_string(), label.value().to_string(), value.0)
+            })
+            .collect();
+        gauges.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            gauges,
+            vec![
+                ("rlimit_soft".to_string(), "cpu".to_string(), f64::INFINITY),
+                ("rlimit_soft".to_string(), "nofile".to_string(), 1024.0),
+                ("rlimit_used".to_string(), "as".to_string(), 4096.0),
+            ]
+        );
+    }
+}
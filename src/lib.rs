@@ -1,4 +1,41 @@
-use libc;
+pub mod address_space;
+mod command;
+mod env;
+mod guard;
+mod instrumentation;
+mod listener;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod open_files;
+mod policy;
+#[cfg(feature = "toml")]
+mod policy_file;
+#[cfg(target_os = "linux")]
+mod prlimit;
+#[cfg(feature = "serde")]
+mod serde_impls;
+mod snapshot;
+mod system;
+mod systemd;
+mod ulimit;
+mod usage;
+
+pub use command::*;
+pub use env::*;
+pub use guard::*;
+pub use listener::*;
+pub use policy::*;
+#[cfg(feature = "toml")]
+pub use policy_file::*;
+#[cfg(target_os = "linux")]
+pub use prlimit::*;
+#[cfg(feature = "serde")]
+pub use serde_impls::FlatLimits;
+pub use snapshot::*;
+pub use system::*;
+pub use systemd::*;
+pub use ulimit::*;
+pub use usage::*;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
 pub enum ResourceLimit {
@@ -7,42 +44,446 @@ pub enum ResourceLimit {
     Value(libc::rlim_t),
 }
 
+impl std::fmt::Display for ResourceLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Infinity => f.pad("unlimited"),
+            Self::Unknown => f.pad("unknown"),
+            Self::Value(n) => f.pad(&n.to_string()),
+        }
+    }
+}
+
+/// An error value returned when parsing a [`ResourceLimit`] from a string
+/// fails.
+#[derive(Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct ParseResourceLimitError {
+    /// The string which was not recognised as a limit.
+    pub input: String,
+}
+
+impl std::fmt::Display for ParseResourceLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid resource limit: {}", self.input)
+    }
+}
+
+impl std::error::Error for ParseResourceLimitError {}
+
+impl std::str::FromStr for ResourceLimit {
+    type Err = ParseResourceLimitError;
+
+    /// Parse a limit as displayed: a number, `unlimited` (or `infinity`), or
+    /// `unknown`, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = s.trim();
+        if value.eq_ignore_ascii_case("unlimited") || value.eq_ignore_ascii_case("infinity") {
+            Ok(ResourceLimit::unlimited())
+        } else if value.eq_ignore_ascii_case("unknown") {
+            Ok(ResourceLimit::Unknown)
+        } else {
+            value
+                .parse()
+                .map(ResourceLimit::Value)
+                .map_err(|_| ParseResourceLimitError {
+                    input: s.to_string(),
+                })
+        }
+    }
+}
+
+// Limit values are `rlim_t`, but sizes, counts and durations elsewhere are
+// `u64`, so convert between the two with these rather than with `as`.
+// `rlim_t` is `u64` on 64-bit targets, but `u32` on 32-bit glibc targets such
+// as i686 and armv7 (unless built with 64-bit file offsets). It is unsigned on
+// every target this crate builds on, as they all provide glibc's
+// `__rlimit_resource_t`; the signed `rlim_t` of e.g. FreeBSD and newlib does
+// not arise.
+
+/// Widen a limit value to a `u64`.
+#[allow(clippy::useless_conversion)] // rlim_t is u64 on 64-bit targets.
+pub(crate) fn rlim_to_u64(n: libc::rlim_t) -> u64 {
+    u64::from(n)
+}
+
+/// Convert a `u64` to a limit. Values which do not fit in `rlim_t`, or which
+/// would collide with `RLIM_INFINITY`, become [`ResourceLimit::Infinity`], as
+/// the kernel reports a limit too large for `rlim_t` as no limit.
+pub(crate) fn limit_from_u64(n: u64) -> ResourceLimit {
+    match libc::rlim_t::try_from(n) {
+        Ok(n) if n != libc::RLIM_INFINITY => ResourceLimit::Value(n),
+        _ => ResourceLimit::Infinity,
+    }
+}
+
+/// Finite soft and hard limits, for use in tests.
+#[cfg(test)]
+pub(crate) fn limits(soft: libc::rlim_t, hard: libc::rlim_t) -> ResourceLimits {
+    ResourceLimits {
+        soft_limit: ResourceLimit::Value(soft),
+        hard_limit: ResourceLimit::Value(hard),
+    }
+}
+
+/// Run the test `name` (its path in the crate, e.g. `guard::tests::foo`) on
+/// its own in a new copy of the test binary, for tests which change the limits
+/// or process group of the whole process. Returns true in that copy, where the
+/// caller should go on with the test; otherwise checks that the copy passed
+/// and returns false.
+#[cfg(test)]
+pub(crate) fn in_isolated_process(name: &str) -> bool {
+    const ISOLATED: &str = "POSIX_RESOURCES_ISOLATED_TEST";
+    if std::env::var_os(ISOLATED).is_some() {
+        return true;
+    }
+    let output = std::process::Command::new(std::env::current_exe().unwrap())
+        .args([name, "--exact", "--test-threads=1"])
+        .env(ISOLATED, "1")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success() && stdout.contains("1 passed"),
+        "{stdout}"
+    );
+    false
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
 pub struct ResourceLimits {
     pub soft_limit: ResourceLimit,
     pub hard_limit: ResourceLimit,
 }
 
-impl From<libc::rlimit> for ResourceLimits {
-    fn from(rs: libc::rlimit) -> Self {
-        let soft_limit = match rs.rlim_cur {
-            libc::RLIM_INFINITY => ResourceLimit::Infinity,
-            other => {
-                if libc::RLIM_SAVED_MAX != libc::RLIM_INFINITY && other == libc::RLIM_SAVED_MAX {
-                    ResourceLimit::Unknown
-                } else if libc::RLIM_SAVED_CUR != libc::RLIM_INFINITY
-                    && other == libc::RLIM_SAVED_CUR
-                {
-                    ResourceLimit::Unknown
-                } else {
-                    ResourceLimit::Value(other)
+impl ResourceLimit {
+    /// No limit, i.e. [`ResourceLimit::Infinity`].
+    pub const fn unlimited() -> ResourceLimit {
+        ResourceLimit::Infinity
+    }
+
+    /// Whether this is no limit, i.e. [`ResourceLimit::Infinity`].
+    pub const fn is_unlimited(&self) -> bool {
+        matches!(self, ResourceLimit::Infinity)
+    }
+
+    /// Get the value of a finite limit, failing for limits which are unlimited
+    /// or unknown.
+    pub fn try_into_u64(&self) -> Result<u64, LimitNotFinite> {
+        match *self {
+            ResourceLimit::Value(n) => Ok(rlim_to_u64(n)),
+            ResourceLimit::Infinity | ResourceLimit::Unknown => Err(LimitNotFinite(*self)),
+        }
+    }
+
+    /// Get the value of a finite limit, clamped to `max`. Limits which are
+    /// unlimited or unknown give `max`.
+    pub fn to_u64_or(&self, max: u64) -> u64 {
+        match *self {
+            ResourceLimit::Value(n) => rlim_to_u64(n).min(max),
+            ResourceLimit::Infinity | ResourceLimit::Unknown => max,
+        }
+    }
+
+    /// Create an RLIMIT_NICE limit from the lowest nice value the process may
+    /// set. The kernel stores this ceiling as `20 - nice`, so a nice value of
+    /// -20 becomes 40 and 19 becomes 1. Values outside of -20..=19 are
+    /// clamped. This transform is only meaningful for RLIMIT_NICE.
+    pub fn from_nice(nice: i32) -> ResourceLimit {
+        ResourceLimit::Value((20 - nice.clamp(-20, 19)) as libc::rlim_t)
+    }
+
+    /// Get the lowest nice value permitted by an RLIMIT_NICE limit; the inverse
+    /// of [`ResourceLimit::from_nice`]. A value of 0 (the usual default for
+    /// unprivileged processes) gives 20, meaning the nice value cannot be
+    /// lowered at all. Returns `None` for unlimited or unknown limits. This
+    /// transform is only meaningful for RLIMIT_NICE.
+    pub fn to_nice(&self) -> Option<i32> {
+        match *self {
+            ResourceLimit::Value(n) => Some(20 - n.min(40) as i32),
+            ResourceLimit::Infinity | ResourceLimit::Unknown => None,
+        }
+    }
+
+    /// Parse a byte limit which may use a `K`, `M`, or `G` suffix for powers of
+    /// 1024 (e.g. `512M`), or be `unlimited` (or `infinity`). Suffixes and
+    /// keywords are not case sensitive. Values which would overflow are
+    /// rejected.
+    pub fn parse_bytes(s: &str) -> Result<ResourceLimit, ParseResourceLimitError> {
+        let err = || ParseResourceLimitError {
+            input: s.to_string(),
+        };
+        let value = s.trim();
+        if value.eq_ignore_ascii_case("unlimited") || value.eq_ignore_ascii_case("infinity") {
+            return Ok(ResourceLimit::unlimited());
+        }
+        let (digits, scale): (&str, libc::rlim_t) = match value.char_indices().last() {
+            Some((i, 'k' | 'K')) => (&value[..i], 1 << 10),
+            Some((i, 'm' | 'M')) => (&value[..i], 1 << 20),
+            Some((i, 'g' | 'G')) => (&value[..i], 1 << 30),
+            _ => (value, 1),
+        };
+        digits
+            .parse::<libc::rlim_t>()
+            .ok()
+            .and_then(|n| n.checked_mul(scale))
+            .map(ResourceLimit::Value)
+            .ok_or_else(err)
+    }
+
+    /// Returns true if this limit is known to be greater than `other`, treating
+    /// [`ResourceLimit::Infinity`] as greater than any value. Comparisons
+    /// involving [`ResourceLimit::Unknown`] are always false.
+    pub(crate) fn exceeds(&self, other: &ResourceLimit) -> bool {
+        match (*self, *other) {
+            (ResourceLimit::Value(a), ResourceLimit::Value(b)) => a > b,
+            (ResourceLimit::Infinity, ResourceLimit::Value(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+/// An error value returned when a limit is required to be a finite value but
+/// is [`ResourceLimit::Infinity`] or [`ResourceLimit::Unknown`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct LimitNotFinite(pub ResourceLimit);
+
+impl std::fmt::Display for LimitNotFinite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "limit is not finite: {}", self.0)
+    }
+}
+
+impl std::error::Error for LimitNotFinite {}
+
+/// An error value returned from the failure of [`ResourceLimits::new`] or
+/// [`ResourceLimitsBuilder::build`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum ResourceLimitsError {
+    /// The soft limit is greater than the hard limit.
+    SoftExceedsHard,
+}
+
+impl std::fmt::Display for ResourceLimitsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::SoftExceedsHard => write!(f, "soft limit exceeds hard limit"),
+        }
+    }
+}
+
+impl std::error::Error for ResourceLimitsError {}
+
+impl ResourceLimits {
+    /// Create limits from a soft and hard limit, checking that the soft limit
+    /// does not exceed the hard limit. Prefer this over constructing the struct
+    /// directly, as setrlimit would otherwise reject inverted limits later.
+    pub fn new(
+        soft_limit: ResourceLimit,
+        hard_limit: ResourceLimit,
+    ) -> Result<ResourceLimits, ResourceLimitsError> {
+        if soft_limit.exceeds(&hard_limit) {
+            return Err(ResourceLimitsError::SoftExceedsHard);
+        }
+        Ok(ResourceLimits {
+            soft_limit,
+            hard_limit,
+        })
+    }
+
+    /// Create limits from soft and hard limits obtained separately, checking
+    /// that the soft limit does not exceed the hard limit. This is the same as
+    /// [`ResourceLimits::new`], named for when the two halves come from
+    /// different sources.
+    pub fn from_parts(
+        soft: ResourceLimit,
+        hard: ResourceLimit,
+    ) -> Result<ResourceLimits, ResourceLimitsError> {
+        ResourceLimits::new(soft, hard)
+    }
+
+    /// Start building limits. See [`ResourceLimitsBuilder`].
+    pub fn builder() -> ResourceLimitsBuilder {
+        ResourceLimitsBuilder::default()
+    }
+
+    /// Convert to a `libc::rlimit`, failing if a finite limit has the same raw
+    /// value as `RLIM_INFINITY` or one of the `RLIM_SAVED_*` sentinels, which
+    /// would silently change its meaning.
+    pub fn try_into_rlimit(self) -> Result<libc::rlimit, SentinelCollision> {
+        for limit in [self.soft_limit, self.hard_limit] {
+            if let ResourceLimit::Value(n) = limit {
+                if n == libc::RLIM_INFINITY || classify_raw(n) != SavedValueKind::NotSaved {
+                    return Err(SentinelCollision(n));
                 }
             }
+        }
+        Ok(self.into())
+    }
+
+    /// Returns false if either limit is [`ResourceLimit::Unknown`]. Unknown
+    /// limits are written back as `RLIM_SAVED_CUR`/`RLIM_SAVED_MAX`, so on
+    /// some platforms setting limits read from the kernel may not preserve the
+    /// exact value that was stored.
+    pub fn is_roundtrip_stable(&self) -> bool {
+        self.soft_limit != ResourceLimit::Unknown && self.hard_limit != ResourceLimit::Unknown
+    }
+
+    /// Returns true if both limits are [`ResourceLimit::Unknown`], meaning
+    /// neither of the values stored by the kernel could be interpreted.
+    pub fn is_fully_unknown(&self) -> bool {
+        self.soft_limit == ResourceLimit::Unknown && self.hard_limit == ResourceLimit::Unknown
+    }
+
+    /// Express the soft and hard limits as plain numbers. Both
+    /// [`ResourceLimit::Infinity`] and [`ResourceLimit::Unknown`] are returned
+    /// as `None`.
+    pub fn as_options(&self) -> (Option<libc::rlim_t>, Option<libc::rlim_t>) {
+        let as_option = |limit: ResourceLimit| match limit {
+            ResourceLimit::Value(n) => Some(n),
+            ResourceLimit::Infinity | ResourceLimit::Unknown => None,
         };
-        let hard_limit = match rs.rlim_max {
-            libc::RLIM_INFINITY => ResourceLimit::Infinity,
-            other => {
-                if libc::RLIM_SAVED_MAX != libc::RLIM_INFINITY && other == libc::RLIM_SAVED_MAX {
-                    ResourceLimit::Unknown
-                } else if libc::RLIM_SAVED_CUR != libc::RLIM_INFINITY
-                    && other == libc::RLIM_SAVED_CUR
-                {
-                    ResourceLimit::Unknown
-                } else {
-                    ResourceLimit::Value(other)
-                }
+        (as_option(self.soft_limit), as_option(self.hard_limit))
+    }
+
+    /// Create limits from a `(soft, hard)` pair of plain numbers, where `None`
+    /// means [`ResourceLimit::Infinity`]. Note that this means `Unknown` does
+    /// not survive a round trip through [`ResourceLimits::as_options`].
+    pub fn from_options((soft, hard): (Option<libc::rlim_t>, Option<libc::rlim_t>)) -> Self {
+        let from_option = |limit: Option<libc::rlim_t>| match limit {
+            Some(n) => ResourceLimit::Value(n),
+            None => ResourceLimit::unlimited(),
+        };
+        ResourceLimits {
+            soft_limit: from_option(soft),
+            hard_limit: from_option(hard),
+        }
+    }
+
+    /// Replace any [`ResourceLimit::Unknown`] limit with `fallback`, e.g.
+    /// [`ResourceLimit::Infinity`].
+    pub fn unwrap_unknown_as(self, fallback: ResourceLimit) -> ResourceLimits {
+        let unwrap = |limit: ResourceLimit| match limit {
+            ResourceLimit::Unknown => fallback,
+            other => other,
+        };
+        ResourceLimits {
+            soft_limit: unwrap(self.soft_limit),
+            hard_limit: unwrap(self.hard_limit),
+        }
+    }
+
+    /// Combine two sets of limits, taking the more restrictive of each soft and
+    /// hard limit. An unknown limit is replaced by the other side's limit.
+    pub fn tighten(self, other: ResourceLimits) -> ResourceLimits {
+        let min = |a: ResourceLimit, b: ResourceLimit| match (a, b) {
+            (ResourceLimit::Unknown, other) | (other, ResourceLimit::Unknown) => other,
+            (a, b) if a.exceeds(&b) => b,
+            (a, _) => a,
+        };
+        ResourceLimits {
+            soft_limit: min(self.soft_limit, other.soft_limit),
+            hard_limit: min(self.hard_limit, other.hard_limit),
+        }
+    }
+
+    /// Compare two sets of limits, treating any limit which is
+    /// [`ResourceLimit::Unknown`] on either side as matching.
+    pub fn eq_ignoring_unknown(&self, other: &ResourceLimits) -> bool {
+        let eq = |a: ResourceLimit, b: ResourceLimit| {
+            a == ResourceLimit::Unknown || b == ResourceLimit::Unknown || a == b
+        };
+        eq(self.soft_limit, other.soft_limit) && eq(self.hard_limit, other.hard_limit)
+    }
+
+    /// The soft limit as a percentage of the hard limit. Both limits being
+    /// unlimited counts as 100%. Returns `None` if only one limit is finite,
+    /// either is unknown, or the hard limit is zero.
+    pub fn soft_percent_of_hard(&self) -> Option<f64> {
+        match (self.soft_limit, self.hard_limit) {
+            (ResourceLimit::Value(_), ResourceLimit::Value(0)) => None,
+            (ResourceLimit::Value(soft), ResourceLimit::Value(hard)) => {
+                Some(soft as f64 / hard as f64 * 100.0)
             }
+            (ResourceLimit::Infinity, ResourceLimit::Infinity) => Some(100.0),
+            _ => None,
+        }
+    }
+}
+
+/// How the hard limit of a [`ResourceLimitsBuilder`] is determined.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum HardLimitSpec {
+    Value(ResourceLimit),
+    SameAsSoft,
+}
+
+/// A builder for [`ResourceLimits`], created by [`ResourceLimits::builder`].
+/// Both limits default to [`ResourceLimit::Infinity`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceLimitsBuilder {
+    soft_limit: ResourceLimit,
+    hard_limit: HardLimitSpec,
+}
+
+impl Default for ResourceLimitsBuilder {
+    fn default() -> Self {
+        ResourceLimitsBuilder {
+            soft_limit: ResourceLimit::unlimited(),
+            hard_limit: HardLimitSpec::Value(ResourceLimit::unlimited()),
+        }
+    }
+}
+
+impl ResourceLimitsBuilder {
+    /// Set the soft limit.
+    pub fn soft(mut self, soft_limit: ResourceLimit) -> Self {
+        self.soft_limit = soft_limit;
+        self
+    }
+
+    /// Set the hard limit.
+    pub fn hard(mut self, hard_limit: ResourceLimit) -> Self {
+        self.hard_limit = HardLimitSpec::Value(hard_limit);
+        self
+    }
+
+    /// Set the hard limit to whatever the soft limit is when the limits are
+    /// built.
+    pub fn hard_same_as_soft(mut self) -> Self {
+        self.hard_limit = HardLimitSpec::SameAsSoft;
+        self
+    }
+
+    /// Set the hard limit to [`ResourceLimit::Infinity`].
+    pub fn hard_unlimited(self) -> Self {
+        self.hard(ResourceLimit::unlimited())
+    }
+
+    /// Build the limits, checking that the soft limit does not exceed the hard
+    /// limit as [`ResourceLimits::new`] does.
+    pub fn build(self) -> Result<ResourceLimits, ResourceLimitsError> {
+        let hard_limit = match self.hard_limit {
+            HardLimitSpec::Value(hard_limit) => hard_limit,
+            HardLimitSpec::SameAsSoft => self.soft_limit,
         };
+        ResourceLimits::new(self.soft_limit, hard_limit)
+    }
+}
+
+/// Displays only the soft (effective) limit of a [`ResourceLimits`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct SoftOnly(pub ResourceLimits);
+
+impl std::fmt::Display for SoftOnly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.soft_limit.fmt(f)
+    }
+}
+
+impl From<(ResourceLimit, ResourceLimit)> for ResourceLimits {
+    /// Create limits from a `(soft, hard)` pair.
+    fn from((soft_limit, hard_limit): (ResourceLimit, ResourceLimit)) -> Self {
         ResourceLimits {
             soft_limit,
             hard_limit,
@@ -50,14 +491,85 @@ impl From<libc::rlimit> for ResourceLimits {
     }
 }
 
-impl Into<libc::rlimit> for ResourceLimits {
-    fn into(self: ResourceLimits) -> libc::rlimit {
-        let rlim_cur = match self.soft_limit {
+impl TryFrom<ResourceLimits> for u64 {
+    type Error = LimitNotFinite;
+
+    /// Get the soft limit, which is the one enforced, failing if it is
+    /// unlimited or unknown.
+    fn try_from(limits: ResourceLimits) -> Result<Self, Self::Error> {
+        limits.soft_limit.try_into_u64()
+    }
+}
+
+/// Which of the saved-value sentinels, if any, a raw limit value matches. See
+/// [`classify_raw`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum SavedValueKind {
+    /// The value is `RLIM_SAVED_CUR`.
+    SavedCur,
+    /// The value is `RLIM_SAVED_MAX`.
+    SavedMax,
+    /// The value is not a saved-value sentinel.
+    NotSaved,
+}
+
+/// Classify a raw limit value as one of the saved-value sentinels. Values
+/// classified as saved are read as [`ResourceLimit::Unknown`].
+///
+/// A sentinel is only recognised where it differs from `RLIM_INFINITY`. On
+/// platforms such as Linux where the sentinels are all equal to
+/// `RLIM_INFINITY`, every value is [`SavedValueKind::NotSaved`].
+pub fn classify_raw(value: libc::rlim_t) -> SavedValueKind {
+    if libc::RLIM_SAVED_MAX != libc::RLIM_INFINITY && value == libc::RLIM_SAVED_MAX {
+        SavedValueKind::SavedMax
+    } else if libc::RLIM_SAVED_CUR != libc::RLIM_INFINITY && value == libc::RLIM_SAVED_CUR {
+        SavedValueKind::SavedCur
+    } else {
+        SavedValueKind::NotSaved
+    }
+}
+
+impl From<libc::rlimit> for ResourceLimits {
+    fn from(rs: libc::rlimit) -> Self {
+        let from_raw = |value: libc::rlim_t| match value {
+            libc::RLIM_INFINITY => ResourceLimit::unlimited(),
+            other => match classify_raw(other) {
+                SavedValueKind::SavedCur | SavedValueKind::SavedMax => ResourceLimit::Unknown,
+                SavedValueKind::NotSaved => ResourceLimit::Value(other),
+            },
+        };
+        ResourceLimits {
+            soft_limit: from_raw(rs.rlim_cur),
+            hard_limit: from_raw(rs.rlim_max),
+        }
+    }
+}
+
+/// An error value returned when a finite limit has the same raw value as one of
+/// the `RLIM_INFINITY` or `RLIM_SAVED_*` sentinels, and so would be read back
+/// as unlimited or unknown. See [`ResourceLimits::try_into_rlimit`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct SentinelCollision(pub libc::rlim_t);
+
+impl std::fmt::Display for SentinelCollision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "limit value {} collides with a sentinel value", self.0)
+    }
+}
+
+impl std::error::Error for SentinelCollision {}
+
+/// Note that this conversion passes finite values through unchecked, so a
+/// value equal to a sentinel changes meaning. Use
+/// [`ResourceLimits::try_into_rlimit`] to detect this.
+impl From<ResourceLimits> for libc::rlimit {
+    fn from(limits: ResourceLimits) -> Self {
+        let rlim_cur = match limits.soft_limit {
             ResourceLimit::Infinity => libc::RLIM_INFINITY,
             ResourceLimit::Unknown => libc::RLIM_SAVED_CUR,
             ResourceLimit::Value(n) => n,
         };
-        let rlim_max = match self.hard_limit {
+        let rlim_max = match limits.hard_limit {
             ResourceLimit::Infinity => libc::RLIM_INFINITY,
             ResourceLimit::Unknown => libc::RLIM_SAVED_MAX,
             ResourceLimit::Value(n) => n,
@@ -66,6 +578,7 @@ impl Into<libc::rlimit> for ResourceLimits {
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Resource {
     CoreFileSize,
     CPUTime,
@@ -76,9 +589,250 @@ pub enum Resource {
     TotalMemory,
 }
 
-impl Into<libc::__rlimit_resource_t> for Resource {
-    fn into(self: Resource) -> libc::__rlimit_resource_t {
+/// The unit in which a resource is measured. See [`Resource::unit`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum ResourceUnit {
+    Bytes,
+    Seconds,
+    /// A number of items, such as file descriptors.
+    Count,
+}
+
+impl std::fmt::Display for ResourceUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match *self {
+            Self::Bytes => "bytes",
+            Self::Seconds => "seconds",
+            Self::Count => "count",
+        })
+    }
+}
+
+/// Every resource known to this crate, in canonical order.
+const ALL_RESOURCES: [Resource; 7] = [
+    Resource::CoreFileSize,
+    Resource::CPUTime,
+    Resource::DataSize,
+    Resource::FileSize,
+    Resource::OpenFiles,
+    Resource::StackSize,
+    Resource::TotalMemory,
+];
+
+/// The number of [`Resource`] variants. The match has no wildcard, so adding a
+/// variant fails to compile here until it is counted, and then fails the check
+/// below until it is also listed in [`ALL_RESOURCES`].
+const RESOURCE_COUNT: usize = match Resource::CoreFileSize {
+    Resource::CoreFileSize
+    | Resource::CPUTime
+    | Resource::DataSize
+    | Resource::FileSize
+    | Resource::OpenFiles
+    | Resource::StackSize
+    | Resource::TotalMemory => 7,
+};
+
+// `ALL_RESOURCES` drives iteration, parsing, and caching, so check that it
+// lists every variant exactly once: it must have one entry per variant, each
+// at the index given by its canonical key, which rules out duplicates and
+// gaps. Each ulimit flag must also be a distinct `-<letter>` so that it can be
+// mapped back to its resource. Mappings which cannot be checked here, such as
+// converting resource codes back to resources and parsing names, are covered
+// by the tests.
+const _: () = {
+    assert!(
+        ALL_RESOURCES.len() == RESOURCE_COUNT,
+        "ALL_RESOURCES must list every resource"
+    );
+    let mut i = 0;
+    while i < ALL_RESOURCES.len() {
+        assert!(
+            ALL_RESOURCES[i].canonical_key() as usize == i,
+            "ALL_RESOURCES must be listed in canonical key order"
+        );
+        let flag = ALL_RESOURCES[i].ulimit_flag().as_bytes();
+        assert!(
+            flag.len() == 2 && flag[0] == b'-',
+            "ulimit flags must be -<letter>"
+        );
+        let mut j = 0;
+        while j < i {
+            assert!(
+                ALL_RESOURCES[j].ulimit_flag().as_bytes()[1] != flag[1],
+                "ulimit flags must be distinct"
+            );
+            j += 1;
+        }
+        i += 1;
+    }
+};
+
+impl Resource {
+    /// All of the resources known to this crate, in the canonical order.
+    pub fn all() -> &'static [Resource] {
+        &ALL_RESOURCES
+    }
+
+    /// The option used by the shell's `ulimit` builtin for this resource, e.g.
+    /// `-n` for [`Resource::OpenFiles`].
+    pub const fn ulimit_flag(&self) -> &'static str {
+        match self {
+            Resource::CoreFileSize => "-c",
+            Resource::CPUTime => "-t",
+            Resource::DataSize => "-d",
+            Resource::FileSize => "-f",
+            Resource::OpenFiles => "-n",
+            Resource::StackSize => "-s",
+            Resource::TotalMemory => "-v",
+        }
+    }
+
+    /// The unit in which this resource's limits (and usage) are measured.
+    pub fn unit(&self) -> ResourceUnit {
+        match self {
+            Resource::CoreFileSize => ResourceUnit::Bytes,
+            Resource::CPUTime => ResourceUnit::Seconds,
+            Resource::DataSize => ResourceUnit::Bytes,
+            Resource::FileSize => ResourceUnit::Bytes,
+            Resource::OpenFiles => ResourceUnit::Count,
+            Resource::StackSize => ResourceUnit::Bytes,
+            Resource::TotalMemory => ResourceUnit::Bytes,
+        }
+    }
+
+    /// Returns false if this platform rejects the resource as invalid. The
+    /// result is determined with a trial call to getrlimit and cached, except
+    /// with the `mock` feature, where each thread's table may differ.
+    pub fn is_supported(&self) -> bool {
+        use std::sync::atomic::{AtomicU8, Ordering};
+        const UNCHECKED: u8 = 0;
+        const SUPPORTED: u8 = 1;
+        const UNSUPPORTED: u8 = 2;
+        static CACHE: [AtomicU8; ALL_RESOURCES.len()] =
+            [const { AtomicU8::new(UNCHECKED) }; ALL_RESOURCES.len()];
+
+        if cfg!(feature = "mock") {
+            return get_resource_limit(*self) != Err(GetRLimitError::Invalid);
+        }
+        let entry = &CACHE[self.canonical_key() as usize];
+        match entry.load(Ordering::Relaxed) {
+            SUPPORTED => true,
+            UNSUPPORTED => false,
+            _ => {
+                let supported = get_resource_limit(*self) != Err(GetRLimitError::Invalid);
+                entry.store(
+                    if supported { SUPPORTED } else { UNSUPPORTED },
+                    Ordering::Relaxed,
+                );
+                supported
+            }
+        }
+    }
+
+    /// Returns true if `a` and `b` map to the same resource code on this
+    /// platform, in which case setting the limit of one also sets the other.
+    pub fn same_kernel_code(a: Resource, b: Resource) -> bool {
+        libc::__rlimit_resource_t::from(a) == libc::__rlimit_resource_t::from(b)
+    }
+
+    /// Returns false if setting this resource's limit has no effect on the
+    /// running process, only on processes started from it.
+    ///
+    /// This is the case for [`Resource::StackSize`]: the stack of the main
+    /// thread is laid out when the program is executed, so changing the limit
+    /// afterwards does not grow or shrink it. It only affects programs
+    /// executed later (and, with some libcs, the default stack size of new
+    /// threads). The other limits take effect immediately.
+    pub fn set_affects_current_process(&self) -> bool {
+        match self {
+            Resource::StackSize => false,
+            Resource::CoreFileSize
+            | Resource::CPUTime
+            | Resource::DataSize
+            | Resource::FileSize
+            | Resource::OpenFiles
+            | Resource::TotalMemory => true,
+        }
+    }
+
+    /// A fixed key used to order resources. This is independent of both the
+    /// declaration order and the platform's resource codes so that sorted
+    /// output stays stable.
+    const fn canonical_key(&self) -> u8 {
         match self {
+            Resource::CoreFileSize => 0,
+            Resource::CPUTime => 1,
+            Resource::DataSize => 2,
+            Resource::FileSize => 3,
+            Resource::OpenFiles => 4,
+            Resource::StackSize => 5,
+            Resource::TotalMemory => 6,
+        }
+    }
+}
+
+impl std::fmt::Display for Resource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match *self {
+            Resource::CoreFileSize => "core",
+            Resource::CPUTime => "cpu",
+            Resource::DataSize => "data",
+            Resource::FileSize => "fsize",
+            Resource::OpenFiles => "nofile",
+            Resource::StackSize => "stack",
+            Resource::TotalMemory => "as",
+        })
+    }
+}
+
+/// An error value returned when parsing a [`Resource`] from a string fails.
+#[derive(Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct ParseResourceError {
+    /// The string which was not recognised as a resource name.
+    pub input: String,
+}
+
+impl std::fmt::Display for ParseResourceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown resource name: {}", self.input)
+    }
+}
+
+impl std::error::Error for ParseResourceError {}
+
+impl std::str::FromStr for Resource {
+    type Err = ParseResourceError;
+
+    /// Parse a resource from its name as displayed (e.g. `nofile`) or its
+    /// constant name (e.g. `RLIMIT_NOFILE`), ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase();
+        let name = name.strip_prefix("rlimit_").unwrap_or(&name);
+        Resource::all()
+            .iter()
+            .find(|resource| resource.to_string() == name)
+            .copied()
+            .ok_or_else(|| ParseResourceError {
+                input: s.to_string(),
+            })
+    }
+}
+
+impl PartialOrd for Resource {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Resource {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.canonical_key().cmp(&other.canonical_key())
+    }
+}
+
+impl From<Resource> for libc::__rlimit_resource_t {
+    fn from(resource: Resource) -> Self {
+        match resource {
             Resource::CoreFileSize => libc::RLIMIT_CORE,
             Resource::CPUTime => libc::RLIMIT_CPU,
             Resource::DataSize => libc::RLIMIT_DATA,
@@ -117,6 +871,16 @@ pub enum GetRLimitError {
     Permission,
 }
 
+impl GetRLimitError {
+    /// The errno value this error was created from.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match *self {
+            Self::Invalid => Some(libc::EINVAL),
+            Self::Permission => Some(libc::EPERM),
+        }
+    }
+}
+
 impl std::fmt::Display for GetRLimitError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
@@ -135,52 +899,2494 @@ impl std::error::Error for GetRLimitError {
     }
 }
 
+impl From<GetRLimitError> for std::io::Error {
+    fn from(err: GetRLimitError) -> Self {
+        match err.raw_os_error() {
+            Some(code) => std::io::Error::from_raw_os_error(code),
+            None => std::io::Error::other(err),
+        }
+    }
+}
+
 /// Get the limit values for a particular resource.
 pub fn get_resource_limit(resource: Resource) -> Result<ResourceLimits, GetRLimitError> {
+    get_resource_limit_annotated(resource).map(|(limits, _)| limits)
+}
+
+/// Which limits returned by [`get_resource_limit_annotated`] were saved-value
+/// sentinels.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct SavedFlags {
+    pub soft: SavedValueKind,
+    pub hard: SavedValueKind,
+}
+
+impl SavedFlags {
+    /// Returns true if either limit was a saved-value sentinel.
+    pub fn any(&self) -> bool {
+        self.soft != SavedValueKind::NotSaved || self.hard != SavedValueKind::NotSaved
+    }
+}
+
+/// Get the limit values for a particular resource as [`get_resource_limit`]
+/// does, along with which of the raw values were saved-value sentinels (see
+/// [`classify_raw`]). This distinguishes an [`ResourceLimit::Unknown`] read
+/// from a sentinel from one constructed by other means.
+pub fn get_resource_limit_annotated(
+    resource: Resource,
+) -> Result<(ResourceLimits, SavedFlags), GetRLimitError> {
     let mut rlimit: libc::rlimit = libc::rlimit {
         rlim_cur: 0,
         rlim_max: 0,
     };
-    unsafe {
-        match libc::getrlimit(resource.into(), &mut rlimit) {
-            0 => Ok(rlimit.into()),
-            -1 => {
-                let errno: *mut libc::c_int = libc::__errno_location();
-                Err(match *errno {
-                    libc::EINVAL => GetRLimitError::Invalid,
-                    libc::EPERM => GetRLimitError::Permission,
-                    _ => panic!("Invalid error code"),
-                })
-            }
-            _ => panic!("Invalid error return"),
-        }
+    match sys_getrlimit(resource, &mut rlimit) {
+        Ok(()) => Ok((
+            rlimit.into(),
+            SavedFlags {
+                soft: classify_raw(rlimit.rlim_cur),
+                hard: classify_raw(rlimit.rlim_max),
+            },
+        )),
+        Err(libc::EINVAL) => Err(GetRLimitError::Invalid),
+        Err(libc::EPERM) => Err(GetRLimitError::Permission),
+        Err(_) => panic!("Invalid error code"),
     }
 }
 
-/// An error value returned from the failure of ['set_resource_limit'].
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
-pub enum SetRLimitError {
-    /// [EINVAL] The limit specified cannot be lowered because current usage is
-    /// already higher than the limit.
-    Invalid,
+/// Call getrlimit, returning the errno value on failure. With the `mock`
+/// feature this reads the [`mock`] table instead.
+fn sys_getrlimit(resource: Resource, rlimit: &mut libc::rlimit) -> Result<(), i32> {
+    #[cfg(feature = "mock")]
+    {
+        mock::getrlimit(resource.into(), rlimit)
+    }
+    #[cfg(not(feature = "mock"))]
+    {
+        check_syscall(unsafe { libc::getrlimit(resource.into(), rlimit) })
+    }
+}
+
+/// Call setrlimit, returning the errno value on failure. With the `mock`
+/// feature this writes to the [`mock`] table instead.
+fn sys_setrlimit(resource: Resource, rlimit: &libc::rlimit) -> Result<(), i32> {
+    #[cfg(feature = "mock")]
+    {
+        mock::setrlimit(resource.into(), rlimit)
+    }
+    #[cfg(not(feature = "mock"))]
+    {
+        check_syscall(unsafe { libc::setrlimit(resource.into(), rlimit) })
+    }
+}
+
+/// Get the errno value left by the last failed call.
+pub(crate) fn last_errno() -> i32 {
+    std::io::Error::last_os_error()
+        .raw_os_error()
+        .expect("last_os_error has an errno")
+}
+
+/// Interpret the return value of a syscall which returns 0 on success and -1
+/// with errno set on failure, returning the errno value on failure. Panics on
+/// any other return value.
+pub(crate) fn check_syscall(ret: libc::c_int) -> Result<(), i32> {
+    match ret {
+        0 => Ok(()),
+        -1 => Err(last_errno()),
+        _ => panic!("Invalid error return"),
+    }
+}
+
+/// The limit values of every resource, as returned by
+/// [`get_all_resource_limits`].
+pub type AllLimits = Vec<(Resource, Result<ResourceLimits, GetRLimitError>)>;
+
+/// Get the limit values for every resource, sorted in the canonical resource
+/// order.
+pub fn get_all_resource_limits() -> AllLimits {
+    Resource::all()
+        .iter()
+        .map(|&resource| {
+            let limits = get_resource_limit(resource);
+            if let Ok(limits) = limits {
+                instrumentation::record_soft_limit(resource, limits.soft_limit);
+            }
+            (resource, limits)
+        })
+        .collect()
+}
+
+/// Get the limit values for every resource as [`get_all_resource_limits`]
+/// does. If `skip_unsupported` is true, resources whose limits are rejected
+/// with EINVAL (meaning the platform does not support them) are omitted
+/// rather than listed as errors.
+pub fn get_all_resource_limits_filtered(skip_unsupported: bool) -> AllLimits {
+    let mut limits = get_all_resource_limits();
+    if skip_unsupported {
+        limits.retain(|(_, limits)| *limits != Err(GetRLimitError::Invalid));
+    }
+    limits
+}
+
+/// Get the limit values for every resource as [`get_all_resource_limits`]
+/// does, along with a list of the resources whose limits were fully unknown
+/// (see [`ResourceLimits::is_fully_unknown`]). A non-empty list usually
+/// indicates a platform quirk in how the kernel reports saved values.
+pub fn get_all_resource_limits_with_warnings() -> (AllLimits, Vec<Resource>) {
+    let limits = get_all_resource_limits();
+    let fully_unknown = limits
+        .iter()
+        .filter(|(_, limits)| limits.is_ok_and(|limits| limits.is_fully_unknown()))
+        .map(|&(resource, _)| resource)
+        .collect();
+    (limits, fully_unknown)
+}
+
+/// Get the `n` resources with the lowest finite soft limits, sorted in
+/// ascending order of the limit. Resources which are unlimited, unknown, or
+/// cannot be read are ignored.
+pub fn lowest_finite_soft_limits(n: usize) -> Vec<(Resource, libc::rlim_t)> {
+    let mut limits: Vec<(Resource, libc::rlim_t)> = get_all_resource_limits()
+        .into_iter()
+        .filter_map(|(resource, limits)| match limits {
+            Ok(ResourceLimits {
+                soft_limit: ResourceLimit::Value(value),
+                ..
+            }) => Some((resource, value)),
+            _ => None,
+        })
+        .collect();
+    limits.sort_by_key(|&(resource, value)| (value, resource));
+    limits.truncate(n);
+    limits
+}
+
+/// Get the resources whose soft limit is known to be below their hard limit,
+/// i.e. those where raising the soft limit would have an effect. An unlimited
+/// hard limit is above any finite soft limit.
+pub fn tunable_resources() -> Vec<Resource> {
+    get_all_resource_limits()
+        .into_iter()
+        .filter(|(_, limits)| {
+            limits.is_ok_and(|limits| limits.hard_limit.exceeds(&limits.soft_limit))
+        })
+        .map(|(resource, _)| resource)
+        .collect()
+}
+
+/// An error value returned from the failure of ['set_resource_limit'].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum SetRLimitError {
+    /// [EINVAL] The limit specified cannot be lowered because current usage is
+    /// already higher than the limit; or the new rlim_cur exceeds the new
+    /// rlim_max.
+    Invalid,
+    /// [EPERM] The limit specified would have raised the maximum limit value,
+    /// and the calling process does not have appropriate privileges.
+    Permission,
+}
+
+impl SetRLimitError {
+    /// The errno value this error was created from.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match *self {
+            Self::Invalid => Some(libc::EINVAL),
+            Self::Permission => Some(libc::EPERM),
+        }
+    }
+}
+
+impl std::fmt::Display for SetRLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Invalid => write!(f, "EINVAL"),
+            Self::Permission => write!(f, "EPERM"),
+        }
+    }
+}
+
+impl std::error::Error for SetRLimitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match *self {
+            Self::Invalid => None,
+            Self::Permission => None,
+        }
+    }
+}
+
+impl From<SetRLimitError> for std::io::Error {
+    fn from(err: SetRLimitError) -> Self {
+        match err.raw_os_error() {
+            Some(code) => std::io::Error::from_raw_os_error(code),
+            None => std::io::Error::other(err),
+        }
+    }
+}
+
+impl From<GetRLimitError> for SetRLimitError {
+    fn from(err: GetRLimitError) -> Self {
+        match err {
+            GetRLimitError::Invalid => SetRLimitError::Invalid,
+            GetRLimitError::Permission => SetRLimitError::Permission,
+        }
+    }
 }
 
 /// Set the limit values for a particular resource.
+///
+/// An unprivileged process may lower its hard limit, but the soft limit must
+/// always be lowered with it: requesting a hard limit below the requested soft
+/// limit fails with [`SetRLimitError::Invalid`], regardless of privilege. Only
+/// an attempt to raise the hard limit above its current value fails with
+/// [`SetRLimitError::Permission`].
 pub fn set_resource_limit(
     resource: Resource,
     r_limit: ResourceLimits,
 ) -> Result<(), SetRLimitError> {
-    unsafe {
-        match libc::setrlimit(resource.into(), &r_limit.into()) {
-            0 => Ok(()),
-            -1 => {
-                let errno: *mut libc::c_int = libc::__errno_location();
-                Err(match *errno {
-                    libc::EINVAL => SetRLimitError::Invalid,
-                    _ => panic!("Invalid error code"),
+    match sys_setrlimit(resource, &r_limit.into()) {
+        Ok(()) => {
+            invalidate_soft_limit_cache();
+            warn_if_clamped(resource, r_limit);
+            notify_limit_changed(resource, r_limit);
+            Ok(())
+        }
+        Err(libc::EINVAL) => Err(SetRLimitError::Invalid),
+        Err(libc::EPERM) => Err(SetRLimitError::Permission),
+        Err(_) => panic!("Invalid error code"),
+    }
+}
+
+/// Set the soft limit of a resource, keeping its current hard limit.
+pub(crate) fn set_soft_limit(
+    resource: Resource,
+    soft_limit: ResourceLimit,
+) -> Result<ResourceLimits, SetRLimitError> {
+    let limits = ResourceLimits {
+        soft_limit,
+        hard_limit: get_resource_limit(resource)?.hard_limit,
+    };
+    set_resource_limit(resource, limits)?;
+    Ok(limits)
+}
+
+/// Whether [`set_resource_limit`] warns about clamped limits. See
+/// [`set_clamp_warning`].
+static CLAMP_WARNING: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Enable or disable a warning printed to stderr whenever
+/// [`set_resource_limit`] succeeds but the limits read back differ from those
+/// requested. This is off by default, and costs an extra getrlimit call per
+/// set while on. Use [`set_resource_limit_strict`] to treat this as an error
+/// instead.
+pub fn set_clamp_warning(enabled: bool) {
+    CLAMP_WARNING.store(enabled, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// Print a warning if clamp warnings are enabled and the limits of `resource`
+/// are not those just set.
+fn warn_if_clamped(resource: Resource, requested: ResourceLimits) {
+    if let Some(warning) = clamp_warning(resource, requested) {
+        eprintln!("{}", warning);
+    }
+}
+
+/// The warning printed by [`warn_if_clamped`], if any.
+fn clamp_warning(resource: Resource, requested: ResourceLimits) -> Option<String> {
+    if !CLAMP_WARNING.load(std::sync::atomic::Ordering::Relaxed) {
+        return None;
+    }
+    let actual = get_resource_limit(resource).ok()?;
+    if stored_as_requested(requested, actual) {
+        return None;
+    }
+    Some(format!(
+        "warning: {} limits were stored as soft {} hard {} instead of the requested soft {} hard {}",
+        resource, actual.soft_limit, actual.hard_limit, requested.soft_limit, requested.hard_limit
+    ))
+}
+
+/// Raise the soft limit of a particular resource to its hard limit, returning
+/// the new limit values.
+pub fn raise_soft_to_hard(resource: Resource) -> Result<ResourceLimits, SetRLimitError> {
+    restore_soft_to_hard(resource).map(|(limits, _)| limits)
+}
+
+/// Raise the soft limit of a particular resource to its hard limit, returning
+/// the new limit values and how much the soft limit was increased by. The
+/// increase is `None` if either the old soft limit or the hard limit is not a
+/// finite value.
+pub fn restore_soft_to_hard(
+    resource: Resource,
+) -> Result<(ResourceLimits, Option<libc::rlim_t>), SetRLimitError> {
+    let limits = get_resource_limit(resource)?;
+    let new_limits = ResourceLimits {
+        soft_limit: limits.hard_limit,
+        hard_limit: limits.hard_limit,
+    };
+    set_resource_limit(resource, new_limits)?;
+    let delta = match (limits.soft_limit, limits.hard_limit) {
+        (ResourceLimit::Value(soft), ResourceLimit::Value(hard)) => Some(hard.saturating_sub(soft)),
+        _ => None,
+    };
+    Ok((new_limits, delta))
+}
+
+/// Adjust the soft limit of a particular resource by `delta`, returning the
+/// new limit values. The result saturates at zero and is capped at the hard
+/// limit.
+///
+/// An unlimited soft limit is left unlimited, as there is no finite value to
+/// adjust. An unknown soft limit cannot be adjusted and results in
+/// [`SetRLimitError::Invalid`].
+pub fn adjust_soft_limit(resource: Resource, delta: i64) -> Result<ResourceLimits, SetRLimitError> {
+    let limits = get_resource_limit(resource)?;
+    let soft_limit = match limits.soft_limit {
+        ResourceLimit::Infinity => ResourceLimit::unlimited(),
+        ResourceLimit::Unknown => return Err(SetRLimitError::Invalid),
+        ResourceLimit::Value(soft) => {
+            let soft = rlim_to_u64(soft);
+            let adjusted = limit_from_u64(if delta >= 0 {
+                soft.saturating_add(delta.unsigned_abs())
+            } else {
+                soft.saturating_sub(delta.unsigned_abs())
+            });
+            if adjusted.exceeds(&limits.hard_limit) {
+                limits.hard_limit
+            } else {
+                adjusted
+            }
+        }
+    };
+    let new_limits = ResourceLimits {
+        soft_limit,
+        hard_limit: limits.hard_limit,
+    };
+    set_resource_limit(resource, new_limits)?;
+    Ok(new_limits)
+}
+
+/// Set the limit values for a particular resource only if the environment
+/// variable `gate_var` is set. A variable which is empty, `0`, or `false` is
+/// treated as unset. Returns whether the limits were applied.
+pub fn apply_if_enabled(
+    gate_var: &str,
+    resource: Resource,
+    limits: ResourceLimits,
+) -> Result<bool, SetRLimitError> {
+    let enabled = match std::env::var_os(gate_var) {
+        Some(value) => {
+            let value = value.to_string_lossy();
+            !(value.is_empty() || value == "0" || value.eq_ignore_ascii_case("false"))
+        }
+        None => false,
+    };
+    if enabled {
+        set_resource_limit(resource, limits)?;
+    }
+    Ok(enabled)
+}
+
+/// Attempt to set both the soft and hard limits of every resource to
+/// unlimited. This usually requires privileges. Each resource is attempted
+/// regardless of earlier failures and its result is returned.
+pub fn reset_all_to_unlimited() -> Vec<(Resource, Result<(), SetRLimitError>)> {
+    let unlimited = ResourceLimits {
+        soft_limit: ResourceLimit::unlimited(),
+        hard_limit: ResourceLimit::unlimited(),
+    };
+    Resource::all()
+        .iter()
+        .map(|&resource| (resource, set_resource_limit(resource, unlimited)))
+        .collect()
+}
+
+/// A difference between expected and actual limits, as found by
+/// [`assert_limits_match`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LimitMismatch {
+    pub resource: Resource,
+    pub expected: ResourceLimits,
+    /// The actual limits, or `None` if the resource was missing from the
+    /// actual limits.
+    pub actual: Option<ResourceLimits>,
+}
+
+/// Compare expected limits against actual limits (such as those returned by
+/// `child_limits`), returning every expected resource whose actual limits
+/// differ or are missing. Resources which are not expected are ignored.
+pub fn assert_limits_match(
+    expected: &[(Resource, ResourceLimits)],
+    actual: &[(Resource, ResourceLimits)],
+) -> Vec<LimitMismatch> {
+    expected
+        .iter()
+        .filter_map(|&(resource, expected)| {
+            let actual = actual
+                .iter()
+                .find(|&&(r, _)| r == resource)
+                .map(|&(_, limits)| limits);
+            if actual == Some(expected) {
+                None
+            } else {
+                Some(LimitMismatch {
+                    resource,
+                    expected,
+                    actual,
+                })
+            }
+        })
+        .collect()
+}
+
+/// Set the limit values for a particular resource, unless they already have
+/// those values. Skipping the setrlimit call in that case also avoids spurious
+/// failures, such as EPERM when "raising" a hard limit to its current value.
+pub fn set_resource_limit_idempotent(
+    resource: Resource,
+    limits: ResourceLimits,
+) -> Result<(), SetRLimitError> {
+    if get_resource_limit(resource)? == limits {
+        return Ok(());
+    }
+    set_resource_limit(resource, limits)
+}
+
+/// Try to set the soft limit on open files to `target`, returning the soft
+/// limit which was actually set.
+///
+/// If `target` is above the hard limit, the hard limit is raised to `target`
+/// as well, which requires privileges. If that fails with
+/// [`SetRLimitError::Permission`] the soft limit is instead raised as far as
+/// the current hard limit allows.
+pub fn try_set_open_files(target: libc::rlim_t) -> Result<libc::rlim_t, SetRLimitError> {
+    let limits = get_resource_limit(Resource::OpenFiles)?;
+    let soft = match limits.hard_limit {
+        ResourceLimit::Value(hard) if target > hard => {
+            let raised = ResourceLimits {
+                soft_limit: ResourceLimit::Value(target),
+                hard_limit: ResourceLimit::Value(target),
+            };
+            match set_resource_limit(Resource::OpenFiles, raised) {
+                Ok(()) => return Ok(target),
+                Err(SetRLimitError::Permission) => hard,
+                Err(err) => return Err(err),
+            }
+        }
+        _ => target,
+    };
+    set_resource_limit(
+        Resource::OpenFiles,
+        ResourceLimits {
+            soft_limit: ResourceLimit::Value(soft),
+            hard_limit: limits.hard_limit,
+        },
+    )?;
+    Ok(soft)
+}
+
+/// Set the soft limit on open files as close to `desired` as the current hard
+/// limit allows, returning the soft limit which was set. Unlike
+/// [`try_set_open_files`] the hard limit is never raised, so this does not
+/// need privileges.
+pub fn best_effort_open_files(desired: libc::rlim_t) -> Result<libc::rlim_t, SetRLimitError> {
+    let limits = get_resource_limit(Resource::OpenFiles)?;
+    let soft = match limits.hard_limit {
+        ResourceLimit::Value(hard) => desired.min(hard),
+        ResourceLimit::Infinity | ResourceLimit::Unknown => desired,
+    };
+    set_resource_limit(
+        Resource::OpenFiles,
+        ResourceLimits {
+            soft_limit: ResourceLimit::Value(soft),
+            hard_limit: limits.hard_limit,
+        },
+    )?;
+    Ok(soft)
+}
+
+/// An error value returned from the failure of
+/// [`set_all_resource_limits_collect`], listing every resource which could not
+/// be set.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BatchError {
+    pub failures: Vec<(Resource, SetRLimitError)>,
+}
+
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to set limits for")?;
+        for (i, (resource, err)) in self.failures.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{}{} ({})", separator, resource, err)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+/// Set the limit values for each of the given resources. Unlike a
+/// transactional update, every resource is attempted and nothing is rolled
+/// back; all failures are returned together.
+pub fn set_all_resource_limits_collect(
+    limits: &[(Resource, ResourceLimits)],
+) -> Result<(), BatchError> {
+    let failures: Vec<(Resource, SetRLimitError)> = limits
+        .iter()
+        .filter_map(|&(resource, limits)| {
+            set_resource_limit(resource, limits)
+                .err()
+                .map(|err| (resource, err))
+        })
+        .collect();
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(BatchError { failures })
+    }
+}
+
+/// Set the soft limit of a particular resource, raising the hard limit to
+/// match if the new soft limit exceeds it. Returns the new limit values.
+///
+/// Both limits are set in a single call, so if the process is not privileged
+/// enough to raise the hard limit this fails with
+/// [`SetRLimitError::Permission`] and neither limit is changed.
+pub fn set_soft_raising_hard_if_needed(
+    resource: Resource,
+    soft_limit: ResourceLimit,
+) -> Result<ResourceLimits, SetRLimitError> {
+    let current = get_resource_limit(resource)?;
+    let hard_limit = if soft_limit.exceeds(&current.hard_limit) {
+        soft_limit
+    } else {
+        current.hard_limit
+    };
+    let limits = ResourceLimits {
+        soft_limit,
+        hard_limit,
+    };
+    set_resource_limit(resource, limits)?;
+    Ok(limits)
+}
+
+/// The number of file descriptors a `fd_set` can hold. Code using `select()`
+/// cannot use descriptors at or above this value, whatever the open files
+/// limit.
+pub const FD_SETSIZE: libc::rlim_t = libc::FD_SETSIZE as libc::rlim_t;
+
+/// Get the number of file descriptors usable by code relying on `select()`:
+/// the soft limit on open files, capped at [`FD_SETSIZE`].
+pub fn effective_select_fd_limit() -> ResourceLimit {
+    match get_resource_limit(Resource::OpenFiles).map(|limits| limits.soft_limit) {
+        Ok(ResourceLimit::Value(n)) => ResourceLimit::Value(n.min(FD_SETSIZE)),
+        _ => ResourceLimit::Value(FD_SETSIZE),
+    }
+}
+
+/// Get the highest open files soft limit which is safe to set: the hard limit,
+/// capped at [`FD_SETSIZE`] if the process uses `select()`, which cannot
+/// handle descriptors at or above that value. An unknown hard limit is
+/// returned as is unless it is capped.
+pub fn safe_max_open_files(uses_select: bool) -> Result<ResourceLimit, GetRLimitError> {
+    let hard_limit = get_resource_limit(Resource::OpenFiles)?.hard_limit;
+    Ok(match hard_limit {
+        ResourceLimit::Value(n) if uses_select => ResourceLimit::Value(n.min(FD_SETSIZE)),
+        ResourceLimit::Infinity | ResourceLimit::Unknown if uses_select => {
+            ResourceLimit::Value(FD_SETSIZE)
+        }
+        hard_limit => hard_limit,
+    })
+}
+
+/// Compute a digest of a set of limits, for cheaply detecting when limits have
+/// drifted from a policy. The pairs are sorted by resource and deduplicated
+/// first, so the order in which they are given does not affect the result.
+///
+/// The digest is stable within a build of this crate, but is not guaranteed to
+/// be the same across Rust versions and should not be persisted.
+pub fn policy_fingerprint(limits: &[(Resource, ResourceLimits)]) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut sorted = limits.to_vec();
+    sorted.sort();
+    sorted.dedup();
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    sorted.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// An error value returned from the failure of [`set_resource_limit_strict`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StrictSetRLimitError {
+    /// The limits were accepted, but the values read back differ from those
+    /// requested.
+    Clamped {
+        requested: ResourceLimits,
+        actual: ResourceLimits,
+    },
+    /// The limits could not be set, or could not be read back.
+    Other(SetRLimitError),
+}
+
+impl std::fmt::Display for StrictSetRLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Clamped { requested, actual } => write!(
+                f,
+                "requested soft {} hard {}, but the kernel stored soft {} hard {}",
+                requested.soft_limit, requested.hard_limit, actual.soft_limit, actual.hard_limit
+            ),
+            Self::Other(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for StrictSetRLimitError {}
+
+/// Returns true if the limits read back after setting `requested` match it.
+/// Requested limits of [`ResourceLimit::Unknown`] are not compared.
+fn stored_as_requested(requested: ResourceLimits, actual: ResourceLimits) -> bool {
+    let matches = |requested: ResourceLimit, actual: ResourceLimit| {
+        requested == ResourceLimit::Unknown || requested == actual
+    };
+    matches(requested.soft_limit, actual.soft_limit)
+        && matches(requested.hard_limit, actual.hard_limit)
+}
+
+/// Set the limit values for a particular resource, then read them back and
+/// fail with [`StrictSetRLimitError::Clamped`] if the kernel stored anything
+/// other than what was requested. Requested limits of
+/// [`ResourceLimit::Unknown`] are not compared.
+///
+/// Linux rejects a soft limit above the hard limit with EINVAL rather than
+/// clamping it, so there that is reported as
+/// [`StrictSetRLimitError::Other`].
+pub fn set_resource_limit_strict(
+    resource: Resource,
+    limits: ResourceLimits,
+) -> Result<(), StrictSetRLimitError> {
+    set_resource_limit(resource, limits).map_err(StrictSetRLimitError::Other)?;
+    let actual =
+        get_resource_limit(resource).map_err(|err| StrictSetRLimitError::Other(err.into()))?;
+    if stored_as_requested(limits, actual) {
+        Ok(())
+    } else {
+        Err(StrictSetRLimitError::Clamped {
+            requested: limits,
+            actual,
+        })
+    }
+}
+
+/// Get the signed change in the soft limit from `old` to `new`, e.g. for
+/// logging the effect of tuning a memory limit. Returns `None` if either soft
+/// limit is not finite, or the change does not fit in an `i64`.
+pub fn memory_limit_delta(old: ResourceLimits, new: ResourceLimits) -> Option<i64> {
+    let old = i128::from(old.soft_limit.try_into_u64().ok()?);
+    let new = i128::from(new.soft_limit.try_into_u64().ok()?);
+    i64::try_from(new - old).ok()
+}
+
+/// A problem with the live limits of a resource, found by [`audit_limits`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum LimitIssue {
+    /// The soft limit is greater than the hard limit.
+    SoftExceedsHard,
+    /// Both limits are unknown (see [`ResourceLimits::is_fully_unknown`]).
+    FullyUnknown,
+    /// At least one limit is unknown, so writing the limits back may not
+    /// preserve them (see [`ResourceLimits::is_roundtrip_stable`]).
+    NotRoundtripStable,
+}
+
+/// An issue with the limits of a particular resource.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct LimitAudit {
+    pub resource: Resource,
+    pub issue: LimitIssue,
+}
+
+/// Check the live limits of every resource for problems. A resource may be
+/// reported more than once if it has several issues. Resources whose limits
+/// cannot be read are not reported.
+pub fn audit_limits() -> Vec<LimitAudit> {
+    let mut audits = Vec::new();
+    for (resource, limits) in get_all_resource_limits() {
+        let limits = match limits {
+            Ok(limits) => limits,
+            Err(_) => continue,
+        };
+        let mut report = |issue| audits.push(LimitAudit { resource, issue });
+        if limits.soft_limit.exceeds(&limits.hard_limit) {
+            report(LimitIssue::SoftExceedsHard);
+        }
+        if limits.is_fully_unknown() {
+            report(LimitIssue::FullyUnknown);
+        }
+        if !limits.is_roundtrip_stable() {
+            report(LimitIssue::NotRoundtripStable);
+        }
+    }
+    audits
+}
+
+/// Set both the soft and hard [`Resource::CPUTime`] limits to the given number
+/// of minutes, returning the new limit values. A number of seconds too large
+/// for a limit value sets no limit.
+///
+/// Lowering the hard limit cannot be undone without privileges.
+pub fn set_cpu_limit_minutes(minutes: u64) -> Result<ResourceLimits, SetRLimitError> {
+    let seconds = limit_from_u64(minutes.saturating_mul(60));
+    let limits = ResourceLimits {
+        soft_limit: seconds,
+        hard_limit: seconds,
+    };
+    set_resource_limit(Resource::CPUTime, limits)?;
+    Ok(limits)
+}
+
+/// Get the soft [`Resource::CPUTime`] limit in whole minutes, rounded down.
+/// Returns `None` if the limit is unlimited or unknown.
+pub fn cpu_limit_minutes() -> Result<Option<u64>, GetRLimitError> {
+    Ok(match get_resource_limit(Resource::CPUTime)?.soft_limit {
+        ResourceLimit::Value(seconds) => Some(rlim_to_u64(seconds) / 60),
+        ResourceLimit::Infinity | ResourceLimit::Unknown => None,
+    })
+}
+
+/// The [`Resource::CPUTime`] limits, in seconds, where `None` means
+/// unlimited.
+///
+/// When the CPU time of the process reaches the soft limit it is sent
+/// SIGXCPU, and then sent SIGXCPU again every second until it reaches the hard
+/// limit, when it is sent SIGKILL. The gap between the two is the grace period
+/// a process has to wind down after being warned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CpuLimitPolicy {
+    pub soft_secs: Option<u64>,
+    pub hard_secs: Option<u64>,
+}
+
+impl CpuLimitPolicy {
+    /// The time between the first SIGXCPU and SIGKILL, or `None` if the hard
+    /// limit is unlimited.
+    pub fn grace_secs(&self) -> Option<u64> {
+        let hard = self.hard_secs?;
+        Some(hard.saturating_sub(self.soft_secs.unwrap_or(hard)))
+    }
+}
+
+impl From<CpuLimitPolicy> for ResourceLimits {
+    fn from(policy: CpuLimitPolicy) -> Self {
+        ResourceLimits {
+            soft_limit: policy
+                .soft_secs
+                .map_or(ResourceLimit::Infinity, limit_from_u64),
+            hard_limit: policy
+                .hard_secs
+                .map_or(ResourceLimit::Infinity, limit_from_u64),
+        }
+    }
+}
+
+/// Set both CPU limits from `policy`. Fails with [`SetRLimitError::Invalid`]
+/// without changing anything if the soft limit exceeds the hard limit.
+pub fn set_cpu_policy(policy: CpuLimitPolicy) -> Result<(), SetRLimitError> {
+    let limits = ResourceLimits::from(policy);
+    if limits.soft_limit.exceeds(&limits.hard_limit) {
+        return Err(SetRLimitError::Invalid);
+    }
+    set_resource_limit(Resource::CPUTime, limits)
+}
+
+/// Get the CPU limits as a [`CpuLimitPolicy`]. Unknown limits are reported as
+/// unlimited.
+pub fn cpu_policy() -> Result<CpuLimitPolicy, GetRLimitError> {
+    let (soft_secs, hard_secs) = get_resource_limit(Resource::CPUTime)?.as_options();
+    Ok(CpuLimitPolicy {
+        soft_secs: soft_secs.map(rlim_to_u64),
+        hard_secs: hard_secs.map(rlim_to_u64),
+    })
+}
+
+/// The tightest soft limit passed to [`ratchet_soft_limit`] for each resource.
+static RATCHET: std::sync::Mutex<std::collections::BTreeMap<Resource, libc::rlim_t>> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+/// Lower the soft limit of a particular resource to `value` if it is currently
+/// higher, returning the resulting limit values. The tightest value seen for
+/// each resource is remembered, and calls with a looser value than that are
+/// ignored, so the limit only ever tightens no matter the order of calls. A
+/// value is remembered even if lowering the limit to it fails.
+pub fn ratchet_soft_limit(
+    resource: Resource,
+    value: libc::rlim_t,
+) -> Result<ResourceLimits, SetRLimitError> {
+    let lock_ratchet = || {
+        RATCHET
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    };
+    // The lock is not held while the limit is set, since that calls the
+    // `on_limit_changed` listeners, which may call this again.
+    {
+        let mut ratchet = lock_ratchet();
+        if ratchet
+            .get(&resource)
+            .is_some_and(|&tightest| value > tightest)
+        {
+            return Ok(get_resource_limit(resource)?);
+        }
+        ratchet.insert(resource, value);
+    }
+    let mut target = value;
+    loop {
+        let limits = get_resource_limit(resource)?;
+        let limits = if limits.soft_limit.exceeds(&ResourceLimit::Value(target)) {
+            set_soft_limit(resource, ResourceLimit::Value(target))?
+        } else {
+            limits
+        };
+        // A tighter value may have been recorded while the limit was being
+        // set, in which case this call could have loosened it again.
+        match lock_ratchet().get(&resource) {
+            Some(&tightest) if tightest < target => target = tightest,
+            _ => return Ok(limits),
+        }
+    }
+}
+
+/// Set the limits of each resource in `required`, taking the limit values from
+/// `limits`. Entries in `limits` for other resources are ignored.
+///
+/// If any required resource has no entry in `limits`, that resource is
+/// returned with [`SetRLimitError::Invalid`] before any limit is set.
+/// Otherwise the first resource which fails to be set is returned along with
+/// the error.
+pub fn apply_required(
+    required: &[Resource],
+    limits: &[(Resource, ResourceLimits)],
+) -> Result<(), (Resource, SetRLimitError)> {
+    if let Some(&missing) = required
+        .iter()
+        .find(|resource| !limits.iter().any(|(r, _)| r == *resource))
+    {
+        return Err((missing, SetRLimitError::Invalid));
+    }
+    for &(resource, limits) in limits {
+        if required.contains(&resource) {
+            set_resource_limit(resource, limits).map_err(|err| (resource, err))?;
+        }
+    }
+    Ok(())
+}
+
+/// Describe the limits of a particular resource on one line, for logging,
+/// e.g. `nofile: soft=1024 hard=1048576 (count)`.
+pub fn describe_resource(resource: Resource) -> Result<String, GetRLimitError> {
+    let limits = get_resource_limit(resource)?;
+    Ok(format!(
+        "{}: soft={} hard={} ({})",
+        resource,
+        limits.soft_limit,
+        limits.hard_limit,
+        resource.unit()
+    ))
+}
+
+/// An error value returned from the failure of [`set_memory_limits`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MemoryLimitError {
+    /// The limits failed validation, and none were set.
+    Inconsistent,
+    /// Setting the limits of a resource failed. Any limits already set were
+    /// rolled back.
+    Set(Resource, SetRLimitError),
+}
+
+impl std::fmt::Display for MemoryLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Inconsistent => write!(f, "inconsistent memory limits"),
+            Self::Set(resource, err) => write!(f, "{}: {}", resource, err),
+        }
+    }
+}
+
+impl std::error::Error for MemoryLimitError {}
+
+/// The default validation rule of [`set_memory_limits`]: the soft address
+/// space limit must be at least the sum of the soft data and stack limits. An
+/// unlimited address space always passes, and a finite one fails if either
+/// of the other two is unlimited. If any of the soft limits is unknown the
+/// check passes.
+pub fn memory_limits_consistent(
+    as_: ResourceLimits,
+    data: ResourceLimits,
+    stack: ResourceLimits,
+) -> bool {
+    match (as_.soft_limit, data.soft_limit, stack.soft_limit) {
+        (ResourceLimit::Unknown, _, _)
+        | (_, ResourceLimit::Unknown, _)
+        | (_, _, ResourceLimit::Unknown) => true,
+        (ResourceLimit::Infinity, _, _) => true,
+        (ResourceLimit::Value(as_), ResourceLimit::Value(data), ResourceLimit::Value(stack)) => {
+            data.checked_add(stack).is_some_and(|sum| sum <= as_)
+        }
+        (ResourceLimit::Value(_), _, _) => false,
+    }
+}
+
+/// Set the [`Resource::TotalMemory`], [`Resource::DataSize`], and
+/// [`Resource::StackSize`] limits together, after checking them with
+/// [`memory_limits_consistent`]. See [`set_memory_limits_with`].
+pub fn set_memory_limits(
+    as_: ResourceLimits,
+    data: ResourceLimits,
+    stack: ResourceLimits,
+) -> Result<(), MemoryLimitError> {
+    set_memory_limits_with(as_, data, stack, memory_limits_consistent)
+}
+
+/// Set the [`Resource::TotalMemory`], [`Resource::DataSize`], and
+/// [`Resource::StackSize`] limits together, after checking them with
+/// `validate`, which is passed the limits in the same order.
+///
+/// If setting any of the limits fails, those already set are restored to
+/// their previous values. Restoring a hard limit which was lowered requires
+/// privileges, so without them the rollback may be incomplete.
+pub fn set_memory_limits_with(
+    as_: ResourceLimits,
+    data: ResourceLimits,
+    stack: ResourceLimits,
+    validate: impl FnOnce(ResourceLimits, ResourceLimits, ResourceLimits) -> bool,
+) -> Result<(), MemoryLimitError> {
+    if !validate(as_, data, stack) {
+        return Err(MemoryLimitError::Inconsistent);
+    }
+    apply_transactional(&[
+        (Resource::TotalMemory, as_),
+        (Resource::DataSize, data),
+        (Resource::StackSize, stack),
+    ])
+    .map_err(|(resource, err)| MemoryLimitError::Set(resource, err))
+}
+
+/// Set each of the given limits in order. If any fails, those already set are
+/// restored to their previous values, as far as privileges allow, and the
+/// failing resource is returned along with the error.
+pub(crate) fn apply_transactional(
+    limits: &[(Resource, ResourceLimits)],
+) -> Result<(), (Resource, SetRLimitError)> {
+    let mut applied: Vec<(Resource, ResourceLimits)> = Vec::new();
+    for &(resource, limits) in limits {
+        let result = get_resource_limit(resource)
+            .map_err(SetRLimitError::from)
+            .and_then(|previous| set_resource_limit(resource, limits).map(|()| previous));
+        match result {
+            Ok(previous) => applied.push((resource, previous)),
+            Err(err) => {
+                for &(resource, previous) in applied.iter().rev() {
+                    let _ = set_resource_limit(resource, previous);
+                }
+                return Err((resource, err));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A change to the limits of a resource, as planned by [`plan_changes`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LimitChange {
+    pub resource: Resource,
+    /// The live limits when the change was planned.
+    pub from: ResourceLimits,
+    pub to: ResourceLimits,
+}
+
+/// Work out which of the desired limits differ from the live limits, so that
+/// only those need to be set. Resources whose live limits cannot be read are
+/// skipped.
+pub fn plan_changes(desired: &[(Resource, ResourceLimits)]) -> Vec<LimitChange> {
+    desired
+        .iter()
+        .filter_map(|&(resource, to)| {
+            let from = get_resource_limit(resource).ok()?;
+            (from != to).then_some(LimitChange { resource, from, to })
+        })
+        .collect()
+}
+
+/// Apply the changes planned by [`plan_changes`]. The first resource which
+/// fails to be set is returned along with the error, and the remaining changes
+/// are not applied.
+pub fn apply_plan(plan: &[LimitChange]) -> Result<(), (Resource, SetRLimitError)> {
+    for change in plan {
+        set_resource_limit(change.resource, change.to).map_err(|err| (change.resource, err))?;
+    }
+    Ok(())
+}
+
+/// The open files soft limit below which [`looks_restricted`] reports a
+/// restricted environment. Typical defaults are 1024 or more.
+const RESTRICTED_OPEN_FILES: libc::rlim_t = 256;
+
+/// The address space and data soft limit, in bytes, below which
+/// [`looks_restricted`] reports a restricted environment.
+const RESTRICTED_MEMORY_BYTES: libc::rlim_t = 1 << 30;
+
+/// Guess whether the process is running in a restricted environment, such as
+/// a sandbox or a tightly configured container or service. This returns true
+/// if any of the following soft limits hold:
+///
+/// - [`Resource::OpenFiles`] is below 256.
+/// - [`Resource::TotalMemory`] is below 1 GiB.
+/// - [`Resource::DataSize`] is below 1 GiB.
+/// - [`Resource::CPUTime`] is finite.
+///
+/// These are deliberately conservative, so many restricted environments (e.g.
+/// those limited by cgroups, which are not reflected in rlimits) are not
+/// detected. Limits which are unlimited, unknown, or cannot be read are not
+/// counted as restrictive.
+pub fn looks_restricted() -> bool {
+    let below = |resource: Resource, threshold: libc::rlim_t| {
+        get_resource_limit(resource)
+            .is_ok_and(|limits| ResourceLimit::Value(threshold).exceeds(&limits.soft_limit))
+    };
+    below(Resource::OpenFiles, RESTRICTED_OPEN_FILES)
+        || below(Resource::TotalMemory, RESTRICTED_MEMORY_BYTES)
+        || below(Resource::DataSize, RESTRICTED_MEMORY_BYTES)
+        || get_resource_limit(Resource::CPUTime)
+            .is_ok_and(|limits| matches!(limits.soft_limit, ResourceLimit::Value(_)))
+}
+
+/// Convert a resource and its limits to the raw values passed to setrlimit or
+/// prlimit.
+pub fn to_raw_pair(
+    resource: Resource,
+    limits: ResourceLimits,
+) -> (libc::__rlimit_resource_t, libc::rlimit) {
+    (resource.into(), limits.into())
+}
+
+/// A limit to set with [`set_resource_limit_partial`], or [`MaybeLimit::Keep`]
+/// to leave the current value as it is.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum MaybeLimit {
+    Set(ResourceLimit),
+    Keep,
+}
+
+impl From<ResourceLimit> for MaybeLimit {
+    fn from(limit: ResourceLimit) -> Self {
+        MaybeLimit::Set(limit)
+    }
+}
+
+/// Set the soft and/or hard limit of a particular resource, taking the
+/// current value for any limit given as [`MaybeLimit::Keep`]. Returns the new
+/// limit values.
+pub fn set_resource_limit_partial(
+    resource: Resource,
+    soft: MaybeLimit,
+    hard: MaybeLimit,
+) -> Result<ResourceLimits, SetRLimitError> {
+    let current = get_resource_limit(resource)?;
+    let choose = |limit: MaybeLimit, current: ResourceLimit| match limit {
+        MaybeLimit::Set(limit) => limit,
+        MaybeLimit::Keep => current,
+    };
+    let limits = ResourceLimits {
+        soft_limit: choose(soft, current.soft_limit),
+        hard_limit: choose(hard, current.hard_limit),
+    };
+    set_resource_limit(resource, limits)?;
+    Ok(limits)
+}
+
+/// Get the limits of every resource as `(resource code, rlim_cur, rlim_max)`
+/// entries in the canonical resource order, for passing to C. The values are
+/// encoded as setrlimit expects: `RLIM_INFINITY` means unlimited, and limits
+/// which are unknown or cannot be read are given as `RLIM_SAVED_CUR` and
+/// `RLIM_SAVED_MAX` (which on Linux are also `RLIM_INFINITY`).
+pub fn all_limits_array() -> [(i32, libc::rlim_t, libc::rlim_t); ALL_RESOURCES.len()] {
+    ALL_RESOURCES.map(|resource| {
+        let limits = get_resource_limit(resource).unwrap_or(ResourceLimits {
+            soft_limit: ResourceLimit::Unknown,
+            hard_limit: ResourceLimit::Unknown,
+        });
+        let (code, rlimit) = to_raw_pair(resource, limits);
+        (code as i32, rlimit.rlim_cur, rlimit.rlim_max)
+    })
+}
+
+/// Format a limit of a particular resource in the resource's natural unit:
+/// sizes in the largest binary unit that represents them exactly (e.g.
+/// `512 MiB`), CPU time in seconds (e.g. `30s`), and counts as plain numbers.
+pub fn format_with_unit(resource: Resource, limit: ResourceLimit) -> String {
+    let n = match limit {
+        ResourceLimit::Value(n) => n,
+        ResourceLimit::Infinity | ResourceLimit::Unknown => return limit.to_string(),
+    };
+    match resource.unit() {
+        ResourceUnit::Bytes => {
+            for (size, suffix) in [(1 << 30, "GiB"), (1 << 20, "MiB"), (1 << 10, "KiB")] {
+                if n != 0 && n % size == 0 {
+                    return format!("{} {}", n / size, suffix);
+                }
+            }
+            format!("{} B", n)
+        }
+        ResourceUnit::Seconds => format!("{}s", n),
+        ResourceUnit::Count => n.to_string(),
+    }
+}
+
+/// A problem with an entry of a policy, found by [`validate_policy`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum PolicyIssue {
+    /// The soft limit of the resource is greater than its hard limit.
+    SoftExceedsHard(Resource),
+    /// A limit of the resource collides with a sentinel value. See
+    /// [`ResourceLimits::try_into_rlimit`].
+    SentinelCollision(Resource, libc::rlim_t),
+    /// The resource is listed more than once.
+    Duplicate(Resource),
+}
+
+impl std::fmt::Display for PolicyIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::SoftExceedsHard(resource) => {
+                write!(f, "{}: soft limit exceeds hard limit", resource)
+            }
+            Self::SentinelCollision(resource, value) => write!(
+                f,
+                "{}: limit value {} collides with a sentinel value",
+                resource, value
+            ),
+            Self::Duplicate(resource) => write!(f, "{}: listed more than once", resource),
+        }
+    }
+}
+
+/// Check every entry of a policy for problems without changing any limits,
+/// returning all of the problems found. Each duplicated resource is reported
+/// once.
+pub fn validate_policy(limits: &[(Resource, ResourceLimits)]) -> Result<(), Vec<PolicyIssue>> {
+    let mut issues = Vec::new();
+    let mut seen = std::collections::BTreeSet::new();
+    let mut duplicates = std::collections::BTreeSet::new();
+    for &(resource, limits) in limits {
+        if !seen.insert(resource) && duplicates.insert(resource) {
+            issues.push(PolicyIssue::Duplicate(resource));
+        }
+        if limits.soft_limit.exceeds(&limits.hard_limit) {
+            issues.push(PolicyIssue::SoftExceedsHard(resource));
+        }
+        if let Err(SentinelCollision(value)) = limits.try_into_rlimit() {
+            issues.push(PolicyIssue::SentinelCollision(resource, value));
+        }
+    }
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(issues)
+    }
+}
+
+/// Describe the limits of every resource as an aligned table, one resource per
+/// line, for logging at startup. Limits are shown with [`format_with_unit`].
+/// Resources which this platform does not support are shown as
+/// `unsupported`.
+pub fn report_string() -> String {
+    let mut report = format!("{:<8} {:>12} {:>12}\n", "resource", "soft", "hard");
+    for (resource, limits) in get_all_resource_limits() {
+        let (soft, hard) = match limits {
+            Ok(limits) => (
+                format_with_unit(resource, limits.soft_limit),
+                format_with_unit(resource, limits.hard_limit),
+            ),
+            Err(GetRLimitError::Invalid) => ("unsupported".to_string(), "unsupported".to_string()),
+            Err(err) => (err.to_string(), err.to_string()),
+        };
+        report.push_str(&format!("{:<8} {:>12} {:>12}\n", resource, soft, hard));
+    }
+    report
+}
+
+/// Get the limits of every resource divided evenly between `workers` workers,
+/// for sizing per-worker budgets. Only the [`Resource::OpenFiles`],
+/// [`Resource::TotalMemory`], and [`Resource::DataSize`] limits are divided
+/// (rounding down); other limits, and limits which are unlimited or unknown,
+/// are returned unchanged. Fails with [`GetRLimitError::Invalid`] if `workers`
+/// is 0.
+pub fn per_worker_limits(workers: usize) -> Result<AllLimits, GetRLimitError> {
+    if workers == 0 {
+        return Err(GetRLimitError::Invalid);
+    }
+    let divide = |limit: ResourceLimit| match limit {
+        ResourceLimit::Value(n) => ResourceLimit::Value(n / workers as libc::rlim_t),
+        other => other,
+    };
+    Ok(get_all_resource_limits()
+        .into_iter()
+        .map(|(resource, limits)| match resource {
+            Resource::OpenFiles | Resource::TotalMemory | Resource::DataSize => (
+                resource,
+                limits.map(|limits| ResourceLimits {
+                    soft_limit: divide(limits.soft_limit),
+                    hard_limit: divide(limits.hard_limit),
+                }),
+            ),
+            Resource::CoreFileSize
+            | Resource::CPUTime
+            | Resource::FileSize
+            | Resource::StackSize => (resource, limits),
+        })
+        .collect())
+}
+
+/// Find any resource whose soft limit exceeds its hard limit, which a racing
+/// prlimit from another process can leave behind, and lower the soft limit to
+/// the hard limit. Returns the repaired resources with their new limits.
+/// Resources which cannot be read or written are skipped.
+pub fn repair_inversions() -> Vec<(Resource, ResourceLimits)> {
+    Resource::all()
+        .iter()
+        .filter_map(|&resource| {
+            let limits = get_resource_limit(resource).ok()?;
+            if !limits.soft_limit.exceeds(&limits.hard_limit) {
+                return None;
+            }
+            let repaired = ResourceLimits {
+                soft_limit: limits.hard_limit,
+                hard_limit: limits.hard_limit,
+            };
+            set_resource_limit(resource, repaired).ok()?;
+            Some((resource, repaired))
+        })
+        .collect()
+}
+
+/// The outcome of setting the limits of one resource with
+/// [`apply_and_report`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChangeReport {
+    pub resource: Resource,
+    /// The limits before the change, if they could be read.
+    pub before: Result<ResourceLimits, GetRLimitError>,
+    /// The limits which were requested.
+    pub after: ResourceLimits,
+    pub result: Result<(), SetRLimitError>,
+}
+
+/// Set each of the given limits, reporting the previous and requested limits
+/// and the outcome of each, e.g. for logging. Every limit is attempted even if
+/// earlier ones fail.
+pub fn apply_and_report(limits: &[(Resource, ResourceLimits)]) -> Vec<ChangeReport> {
+    limits
+        .iter()
+        .map(|&(resource, after)| ChangeReport {
+            resource,
+            before: get_resource_limit(resource),
+            after,
+            result: set_resource_limit(resource, after),
+        })
+        .collect()
+}
+
+/// Add up the soft limits of the given resources, e.g. to find the total
+/// memory budget of several byte resources. The resources should share a
+/// unit. The sum saturates rather than overflowing, and is `None` if any of
+/// the soft limits is unlimited or unknown.
+pub fn sum_finite_soft(resources: &[Resource]) -> Result<Option<u64>, GetRLimitError> {
+    let mut total: u64 = 0;
+    for &resource in resources {
+        match get_resource_limit(resource)?.soft_limit {
+            ResourceLimit::Value(n) => total = total.saturating_add(rlim_to_u64(n)),
+            ResourceLimit::Infinity | ResourceLimit::Unknown => return Ok(None),
+        }
+    }
+    Ok(Some(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resource_mappings_cover_every_resource() {
+        let expected = [
+            (Resource::CoreFileSize, ResourceUnit::Bytes, "-c"),
+            (Resource::CPUTime, ResourceUnit::Seconds, "-t"),
+            (Resource::DataSize, ResourceUnit::Bytes, "-d"),
+            (Resource::FileSize, ResourceUnit::Bytes, "-f"),
+            (Resource::OpenFiles, ResourceUnit::Count, "-n"),
+            (Resource::StackSize, ResourceUnit::Bytes, "-s"),
+            (Resource::TotalMemory, ResourceUnit::Bytes, "-v"),
+        ];
+        assert_eq!(Resource::all().len(), RESOURCE_COUNT);
+        assert_eq!(Resource::all().len(), expected.len());
+        for (&resource, &(expected, unit, flag)) in Resource::all().iter().zip(&expected) {
+            assert_eq!(resource, expected);
+            assert_eq!(resource.unit(), unit);
+            assert_eq!(resource.ulimit_flag(), flag);
+            let code = libc::__rlimit_resource_t::from(resource);
+            assert_eq!(Resource::from(code), resource);
+            let name = resource.to_string();
+            assert_eq!(name.parse::<Resource>(), Ok(resource));
+            let constant = format!("RLIMIT_{}", name.to_uppercase());
+            assert_eq!(constant.parse::<Resource>(), Ok(resource));
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn set_resource_limit_classifies_errors() {
+        mock::set_table(&[(Resource::OpenFiles, limits(10, 100))]);
+        assert_eq!(
+            set_resource_limit(Resource::OpenFiles, limits(50, 20)),
+            Err(SetRLimitError::Invalid)
+        );
+        assert_eq!(
+            set_resource_limit(Resource::OpenFiles, limits(10, 200)),
+            Err(SetRLimitError::Permission)
+        );
+        assert_eq!(
+            set_resource_limit(Resource::StackSize, limits(10, 100)),
+            Err(SetRLimitError::Invalid)
+        );
+        assert_eq!(mock::table(), vec![(Resource::OpenFiles, limits(10, 100))]);
+    }
+
+    #[test]
+    fn parse_resource_reports_input() {
+        assert_eq!(" NoFile ".parse::<Resource>(), Ok(Resource::OpenFiles));
+        assert_eq!(
+            "nofiles".parse::<Resource>(),
+            Err(ParseResourceError {
+                input: "nofiles".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn nice_transform_round_trips() {
+        assert_eq!(ResourceLimit::from_nice(-20), ResourceLimit::Value(40));
+        assert_eq!(ResourceLimit::from_nice(19), ResourceLimit::Value(1));
+        assert_eq!(ResourceLimit::from_nice(-100), ResourceLimit::Value(40));
+        assert_eq!(ResourceLimit::from_nice(100), ResourceLimit::Value(1));
+        for nice in -20..=19 {
+            assert_eq!(ResourceLimit::from_nice(nice).to_nice(), Some(nice));
+        }
+        assert_eq!(ResourceLimit::Value(0).to_nice(), Some(20));
+        assert_eq!(ResourceLimit::Infinity.to_nice(), None);
+    }
+
+    #[test]
+    fn try_into_rlimit_rejects_sentinels() {
+        let rlimit = limits(10, 100).try_into_rlimit().unwrap();
+        assert_eq!((rlimit.rlim_cur, rlimit.rlim_max), (10, 100));
+        let collision = ResourceLimits {
+            soft_limit: ResourceLimit::Value(libc::RLIM_INFINITY),
+            hard_limit: ResourceLimit::Infinity,
+        };
+        assert_eq!(
+            collision.try_into_rlimit().err(),
+            Some(SentinelCollision(libc::RLIM_INFINITY))
+        );
+    }
+
+    #[test]
+    fn builder_derives_hard_limit() {
+        let soft = ResourceLimit::Value(10);
+        assert_eq!(
+            ResourceLimits::builder()
+                .soft(soft)
+                .hard_same_as_soft()
+                .build(),
+            Ok(limits(10, 10))
+        );
+        assert_eq!(
+            ResourceLimits::builder().soft(soft).build(),
+            Ok(ResourceLimits {
+                soft_limit: soft,
+                hard_limit: ResourceLimit::Infinity,
+            })
+        );
+        assert_eq!(
+            ResourceLimits::builder()
+                .soft(soft)
+                .hard(ResourceLimit::Value(5))
+                .build(),
+            Err(ResourceLimitsError::SoftExceedsHard)
+        );
+    }
+
+    #[test]
+    fn stored_as_requested_detects_clamping() {
+        assert!(stored_as_requested(limits(10, 100), limits(10, 100)));
+        assert!(!stored_as_requested(limits(200, 100), limits(100, 100)));
+        let keep_hard = ResourceLimits {
+            soft_limit: ResourceLimit::Value(10),
+            hard_limit: ResourceLimit::Unknown,
+        };
+        assert!(stored_as_requested(keep_hard, limits(10, 100)));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn strict_set_reports_rejected_soft_above_hard() {
+        mock::set_table(&[(Resource::OpenFiles, limits(10, 100))]);
+        assert_eq!(
+            set_resource_limit_strict(Resource::OpenFiles, limits(200, 100)),
+            Err(StrictSetRLimitError::Other(SetRLimitError::Invalid))
+        );
+        assert_eq!(
+            set_resource_limit_strict(Resource::OpenFiles, limits(50, 100)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn parse_bytes_scales_suffixes() {
+        assert_eq!(
+            ResourceLimit::parse_bytes("512"),
+            Ok(ResourceLimit::Value(512))
+        );
+        assert_eq!(
+            ResourceLimit::parse_bytes("4k"),
+            Ok(ResourceLimit::Value(4096))
+        );
+        assert_eq!(
+            ResourceLimit::parse_bytes(" 512M "),
+            Ok(ResourceLimit::Value(512 << 20))
+        );
+        assert_eq!(
+            ResourceLimit::parse_bytes("2G"),
+            Ok(ResourceLimit::Value(2 << 30))
+        );
+        assert_eq!(
+            ResourceLimit::parse_bytes("Unlimited"),
+            Ok(ResourceLimit::Infinity)
+        );
+        for input in ["", "M", "1.5G", "-1K", "12T", "20000000000G"] {
+            assert_eq!(
+                ResourceLimit::parse_bytes(input),
+                Err(ParseResourceLimitError {
+                    input: input.to_string()
                 })
+            );
+        }
+    }
+
+    #[test]
+    fn formats_in_natural_unit() {
+        let format = |resource, n| format_with_unit(resource, ResourceLimit::Value(n));
+        assert_eq!(format(Resource::TotalMemory, 512 << 20), "512 MiB");
+        assert_eq!(format(Resource::TotalMemory, 2 << 30), "2 GiB");
+        assert_eq!(format(Resource::StackSize, 1536 << 10), "1536 KiB");
+        assert_eq!(format(Resource::FileSize, 1000), "1000 B");
+        assert_eq!(format(Resource::CoreFileSize, 0), "0 B");
+        assert_eq!(format(Resource::CPUTime, 30), "30s");
+        assert_eq!(format(Resource::OpenFiles, 1024), "1024");
+        assert_eq!(
+            format_with_unit(Resource::TotalMemory, ResourceLimit::Infinity),
+            ResourceLimit::Infinity.to_string()
+        );
+    }
+
+    #[test]
+    fn check_syscall_reads_errno() {
+        assert_eq!(check_syscall(0), Ok(()));
+        for errno in [libc::EINVAL, libc::EPERM] {
+            unsafe { *libc::__errno_location() = errno };
+            assert_eq!(check_syscall(-1), Err(errno));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid error return")]
+    fn check_syscall_rejects_other_returns() {
+        let _ = check_syscall(1);
+    }
+
+    #[test]
+    fn validate_policy_reports_every_issue() {
+        assert_eq!(
+            validate_policy(&[
+                (Resource::OpenFiles, limits(10, 100)),
+                (Resource::StackSize, limits(10, 100)),
+            ]),
+            Ok(())
+        );
+        let sentinel = ResourceLimits {
+            soft_limit: ResourceLimit::Value(libc::RLIM_INFINITY),
+            hard_limit: ResourceLimit::Infinity,
+        };
+        assert_eq!(
+            validate_policy(&[
+                (Resource::OpenFiles, limits(200, 100)),
+                (Resource::StackSize, sentinel),
+                (Resource::OpenFiles, limits(10, 100)),
+                (Resource::OpenFiles, limits(10, 100)),
+            ]),
+            Err(vec![
+                PolicyIssue::SoftExceedsHard(Resource::OpenFiles),
+                PolicyIssue::SentinelCollision(Resource::StackSize, libc::RLIM_INFINITY),
+                PolicyIssue::Duplicate(Resource::OpenFiles),
+            ])
+        );
+    }
+
+    #[test]
+    fn tighten_takes_the_stricter_limits() {
+        assert_eq!(limits(10, 200).tighten(limits(20, 100)), limits(10, 100));
+        let open = ResourceLimits {
+            soft_limit: ResourceLimit::Infinity,
+            hard_limit: ResourceLimit::Unknown,
+        };
+        assert_eq!(open.tighten(limits(20, 100)), limits(20, 100));
+        assert_eq!(limits(20, 100).tighten(open), limits(20, 100));
+        assert_eq!(open.tighten(open), open);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn repair_inversions_lowers_soft_to_hard() {
+        mock::set_table(&[
+            (Resource::OpenFiles, limits(500, 100)),
+            (Resource::StackSize, limits(8, 16)),
+        ]);
+        assert_eq!(
+            repair_inversions(),
+            vec![(Resource::OpenFiles, limits(100, 100))]
+        );
+        assert_eq!(
+            mock::table(),
+            vec![
+                (Resource::OpenFiles, limits(100, 100)),
+                (Resource::StackSize, limits(8, 16)),
+            ]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn sum_finite_soft_adds_soft_limits() {
+        mock::set_table(&[
+            (Resource::DataSize, limits(5, 100)),
+            (Resource::StackSize, limits(10, 100)),
+            (
+                Resource::TotalMemory,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Infinity,
+                    hard_limit: ResourceLimit::Infinity,
+                },
+            ),
+        ]);
+        assert_eq!(
+            sum_finite_soft(&[Resource::DataSize, Resource::StackSize]),
+            Ok(Some(15))
+        );
+        assert_eq!(
+            sum_finite_soft(&[Resource::DataSize, Resource::TotalMemory]),
+            Ok(None)
+        );
+        assert_eq!(
+            sum_finite_soft(&[Resource::DataSize, Resource::OpenFiles]),
+            Err(GetRLimitError::Invalid)
+        );
+    }
+
+    #[test]
+    fn soft_percent_of_hard_handles_unlimited() {
+        assert_eq!(limits(25, 100).soft_percent_of_hard(), Some(25.0));
+        assert_eq!(limits(1, 0).soft_percent_of_hard(), None);
+        let unlimited = ResourceLimits {
+            soft_limit: ResourceLimit::Infinity,
+            hard_limit: ResourceLimit::Infinity,
+        };
+        assert_eq!(unlimited.soft_percent_of_hard(), Some(100.0));
+        let finite_soft = ResourceLimits {
+            soft_limit: ResourceLimit::Value(10),
+            hard_limit: ResourceLimit::Infinity,
+        };
+        assert_eq!(finite_soft.soft_percent_of_hard(), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn open_files_helpers_respect_the_hard_limit() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        assert_eq!(best_effort_open_files(8192), Ok(4096));
+        assert_eq!(
+            mock::table(),
+            vec![(Resource::OpenFiles, limits(4096, 4096))]
+        );
+        assert_eq!(best_effort_open_files(512), Ok(512));
+        // Raising the hard limit fails without privileges, so the soft limit
+        // is raised to the hard limit instead.
+        assert_eq!(try_set_open_files(8192), Ok(4096));
+        assert_eq!(
+            mock::table(),
+            vec![(Resource::OpenFiles, limits(4096, 4096))]
+        );
+    }
+
+    #[test]
+    fn limit_from_u64_never_builds_the_sentinel() {
+        assert_eq!(limit_from_u64(5), ResourceLimit::Value(5));
+        assert_eq!(limit_from_u64(u64::MAX), ResourceLimit::Infinity);
+        assert_eq!(
+            limit_from_u64(rlim_to_u64(libc::RLIM_INFINITY)),
+            ResourceLimit::Infinity
+        );
+    }
+
+    #[test]
+    fn get_all_resource_limits_is_in_canonical_order() {
+        assert!(Resource::all().windows(2).all(|pair| pair[0] < pair[1]));
+        let order = || {
+            get_all_resource_limits()
+                .into_iter()
+                .map(|(resource, _)| resource)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(order(), Resource::all());
+        assert_eq!(order(), order());
+    }
+
+    #[test]
+    fn options_round_trip() {
+        assert_eq!(limits(10, 100).as_options(), (Some(10), Some(100)));
+        let pairs = [(Some(10), Some(100)), (Some(10), None), (None, None)];
+        for pair in pairs {
+            assert_eq!(ResourceLimits::from_options(pair).as_options(), pair);
+        }
+        // Unknown becomes None, which is read back as Infinity.
+        let unknown = ResourceLimits {
+            soft_limit: ResourceLimit::Unknown,
+            hard_limit: ResourceLimit::Value(100),
+        };
+        assert_eq!(unknown.as_options(), (None, Some(100)));
+        assert_eq!(
+            ResourceLimits::from_options(unknown.as_options()),
+            ResourceLimits {
+                soft_limit: ResourceLimit::Infinity,
+                hard_limit: ResourceLimit::Value(100),
             }
-            _ => panic!("Invalid error return"),
+        );
+    }
+
+    #[test]
+    fn soft_only_displays_the_soft_limit() {
+        assert_eq!(SoftOnly(limits(10, 100)).to_string(), "10");
+        let unlimited = ResourceLimits {
+            soft_limit: ResourceLimit::Infinity,
+            hard_limit: ResourceLimit::Infinity,
+        };
+        assert_eq!(SoftOnly(unlimited).to_string(), "unlimited");
+    }
+
+    #[test]
+    fn tuple_converts_as_soft_then_hard() {
+        assert_eq!(
+            ResourceLimits::from((ResourceLimit::Value(10), ResourceLimit::Value(100))),
+            limits(10, 100)
+        );
+    }
+
+    #[test]
+    fn new_rejects_inverted_limits() {
+        assert_eq!(
+            ResourceLimits::new(ResourceLimit::Value(10), ResourceLimit::Value(100)),
+            Ok(limits(10, 100))
+        );
+        assert_eq!(
+            ResourceLimits::new(ResourceLimit::Value(100), ResourceLimit::Value(100)),
+            Ok(limits(100, 100))
+        );
+        assert_eq!(
+            ResourceLimits::new(ResourceLimit::Value(100), ResourceLimit::Value(10)),
+            Err(ResourceLimitsError::SoftExceedsHard)
+        );
+        assert_eq!(
+            ResourceLimits::new(ResourceLimit::Infinity, ResourceLimit::Value(10)),
+            Err(ResourceLimitsError::SoftExceedsHard)
+        );
+    }
+
+    #[test]
+    fn unknown_limits_are_not_roundtrip_stable() {
+        assert!(limits(10, 100).is_roundtrip_stable());
+        let unknown = ResourceLimits {
+            soft_limit: ResourceLimit::Value(10),
+            hard_limit: ResourceLimit::Unknown,
+        };
+        assert!(!unknown.is_roundtrip_stable());
+    }
+
+    #[test]
+    fn fully_unknown_needs_both_limits_unknown() {
+        let unknown = ResourceLimits {
+            soft_limit: ResourceLimit::Unknown,
+            hard_limit: ResourceLimit::Unknown,
+        };
+        assert!(unknown.is_fully_unknown());
+        let half = ResourceLimits {
+            soft_limit: ResourceLimit::Unknown,
+            hard_limit: ResourceLimit::Infinity,
+        };
+        assert!(!half.is_fully_unknown());
+        assert!(!limits(10, 100).is_fully_unknown());
+    }
+
+    #[test]
+    fn try_into_u64_requires_a_finite_limit() {
+        assert_eq!(ResourceLimit::Value(10).try_into_u64(), Ok(10));
+        assert_eq!(
+            ResourceLimit::Infinity.try_into_u64(),
+            Err(LimitNotFinite(ResourceLimit::Infinity))
+        );
+        assert_eq!(
+            ResourceLimit::Unknown.try_into_u64(),
+            Err(LimitNotFinite(ResourceLimit::Unknown))
+        );
+    }
+
+    #[test]
+    fn classify_raw_recognises_distinct_sentinels() {
+        assert_eq!(classify_raw(1024), SavedValueKind::NotSaved);
+        // On Linux the sentinels equal RLIM_INFINITY, so are not recognised.
+        let expected = if libc::RLIM_SAVED_CUR != libc::RLIM_INFINITY {
+            SavedValueKind::SavedCur
+        } else {
+            SavedValueKind::NotSaved
+        };
+        assert_eq!(classify_raw(libc::RLIM_SAVED_CUR), expected);
+    }
+
+    #[test]
+    fn unwrap_unknown_as_replaces_unknown_limits() {
+        let unknown_soft = ResourceLimits {
+            soft_limit: ResourceLimit::Unknown,
+            hard_limit: ResourceLimit::Value(100),
+        };
+        assert_eq!(
+            unknown_soft.unwrap_unknown_as(ResourceLimit::Value(10)),
+            limits(10, 100)
+        );
+        let unknown_hard = ResourceLimits {
+            soft_limit: ResourceLimit::Value(10),
+            hard_limit: ResourceLimit::Unknown,
+        };
+        assert_eq!(
+            unknown_hard.unwrap_unknown_as(ResourceLimit::Infinity),
+            ResourceLimits {
+                soft_limit: ResourceLimit::Value(10),
+                hard_limit: ResourceLimit::Infinity,
+            }
+        );
+        assert_eq!(
+            limits(10, 100).unwrap_unknown_as(ResourceLimit::Infinity),
+            limits(10, 100)
+        );
+    }
+
+    #[test]
+    fn eq_ignoring_unknown_only_skips_unknown_limits() {
+        let unknown_soft = ResourceLimits {
+            soft_limit: ResourceLimit::Unknown,
+            hard_limit: ResourceLimit::Value(100),
+        };
+        assert!(unknown_soft.eq_ignoring_unknown(&limits(10, 100)));
+        assert!(limits(10, 100).eq_ignoring_unknown(&unknown_soft));
+        assert!(!unknown_soft.eq_ignoring_unknown(&limits(10, 200)));
+        assert!(!limits(10, 100).eq_ignoring_unknown(&limits(20, 100)));
+    }
+
+    #[test]
+    fn to_u64_or_clamps_to_max() {
+        assert_eq!(ResourceLimit::Value(10).to_u64_or(100), 10);
+        assert_eq!(ResourceLimit::Value(1000).to_u64_or(100), 100);
+        assert_eq!(ResourceLimit::Infinity.to_u64_or(100), 100);
+        assert_eq!(ResourceLimit::Unknown.to_u64_or(100), 100);
+    }
+
+    #[test]
+    fn from_parts_rejects_inverted_limits() {
+        assert_eq!(
+            ResourceLimits::from_parts(ResourceLimit::Value(10), ResourceLimit::Infinity),
+            Ok(ResourceLimits {
+                soft_limit: ResourceLimit::Value(10),
+                hard_limit: ResourceLimit::Infinity,
+            })
+        );
+        assert_eq!(
+            ResourceLimits::from_parts(ResourceLimit::Value(100), ResourceLimit::Value(10)),
+            Err(ResourceLimitsError::SoftExceedsHard)
+        );
+    }
+
+    #[test]
+    fn converts_the_soft_limit_to_u64() {
+        assert_eq!(u64::try_from(limits(10, 100)), Ok(10));
+        let unlimited = ResourceLimits {
+            soft_limit: ResourceLimit::Infinity,
+            hard_limit: ResourceLimit::Infinity,
+        };
+        assert_eq!(
+            u64::try_from(unlimited),
+            Err(LimitNotFinite(ResourceLimit::Infinity))
+        );
+    }
+
+    #[test]
+    fn unlimited_is_infinity() {
+        assert_eq!(ResourceLimit::unlimited(), ResourceLimit::Infinity);
+        assert!(ResourceLimit::unlimited().is_unlimited());
+        assert!(!ResourceLimit::Value(10).is_unlimited());
+        assert!(!ResourceLimit::Unknown.is_unlimited());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn lowest_finite_soft_limits_sorts_ascending() {
+        mock::set_table(&[
+            (Resource::CPUTime, limits(60, 120)),
+            (
+                Resource::DataSize,
+                ResourceLimits::from_options((None, None)),
+            ),
+            (Resource::OpenFiles, limits(1024, 4096)),
+            (Resource::StackSize, limits(8, 16)),
+        ]);
+        assert_eq!(
+            lowest_finite_soft_limits(2),
+            vec![(Resource::StackSize, 8), (Resource::CPUTime, 60)]
+        );
+        assert_eq!(
+            lowest_finite_soft_limits(10),
+            vec![
+                (Resource::StackSize, 8),
+                (Resource::CPUTime, 60),
+                (Resource::OpenFiles, 1024),
+            ]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn restore_soft_to_hard_reports_the_increase() {
+        mock::set_table(&[
+            (Resource::OpenFiles, limits(1024, 4096)),
+            (
+                Resource::StackSize,
+                ResourceLimits::from_options((Some(8), None)),
+            ),
+        ]);
+        assert_eq!(
+            restore_soft_to_hard(Resource::OpenFiles),
+            Ok((limits(4096, 4096), Some(3072)))
+        );
+        assert_eq!(
+            restore_soft_to_hard(Resource::StackSize),
+            Ok((ResourceLimits::from_options((None, None)), None))
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn adjust_soft_limit_saturates_and_caps() {
+        mock::set_table(&[
+            (Resource::OpenFiles, limits(1024, 4096)),
+            (
+                Resource::StackSize,
+                ResourceLimits::from_options((None, None)),
+            ),
+        ]);
+        assert_eq!(
+            adjust_soft_limit(Resource::OpenFiles, 100),
+            Ok(limits(1124, 4096))
+        );
+        assert_eq!(
+            adjust_soft_limit(Resource::OpenFiles, -200),
+            Ok(limits(924, 4096))
+        );
+        assert_eq!(
+            adjust_soft_limit(Resource::OpenFiles, 10_000),
+            Ok(limits(4096, 4096))
+        );
+        assert_eq!(
+            adjust_soft_limit(Resource::OpenFiles, -10_000),
+            Ok(limits(0, 4096))
+        );
+        assert_eq!(mock::table()[0], (Resource::OpenFiles, limits(0, 4096)));
+        assert_eq!(
+            adjust_soft_limit(Resource::StackSize, -10),
+            Ok(ResourceLimits::from_options((None, None)))
+        );
+    }
+
+    #[test]
+    fn apply_if_enabled_checks_the_variable() {
+        const GATE: &str = "POSIX_RESOURCES_TEST_APPLY_IF_ENABLED";
+        std::env::remove_var(GATE);
+        assert_eq!(
+            apply_if_enabled(GATE, Resource::OpenFiles, limits(512, 4096)),
+            Ok(false)
+        );
+        for value in ["", "0", "false", "FALSE"] {
+            std::env::set_var(GATE, value);
+            assert_eq!(
+                apply_if_enabled(GATE, Resource::OpenFiles, limits(512, 4096)),
+                Ok(false)
+            );
+        }
+        #[cfg(feature = "mock")]
+        {
+            mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+            std::env::set_var(GATE, "1");
+            assert_eq!(
+                apply_if_enabled(GATE, Resource::OpenFiles, limits(512, 4096)),
+                Ok(true)
+            );
+            assert_eq!(
+                mock::table(),
+                vec![(Resource::OpenFiles, limits(512, 4096))]
+            );
+        }
+        std::env::remove_var(GATE);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn reset_all_to_unlimited_reports_every_resource() {
+        mock::set_table(&[
+            (Resource::OpenFiles, limits(1024, 4096)),
+            (
+                Resource::StackSize,
+                ResourceLimits::from_options((Some(8), None)),
+            ),
+        ]);
+        let results = reset_all_to_unlimited();
+        assert_eq!(
+            results
+                .iter()
+                .map(|&(resource, _)| resource)
+                .collect::<Vec<_>>(),
+            Resource::all()
+        );
+        for (resource, result) in results {
+            let expected = match resource {
+                // Raising a finite hard limit needs privileges.
+                Resource::OpenFiles => Err(SetRLimitError::Permission),
+                Resource::StackSize => Ok(()),
+                _ => Err(SetRLimitError::Invalid),
+            };
+            assert_eq!(result, expected, "{}", resource);
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn idempotent_set_only_changes_differing_limits() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        assert_eq!(
+            set_resource_limit_idempotent(Resource::OpenFiles, limits(1024, 4096)),
+            Ok(())
+        );
+        assert_eq!(
+            mock::table(),
+            vec![(Resource::OpenFiles, limits(1024, 4096))]
+        );
+        assert_eq!(
+            set_resource_limit_idempotent(Resource::OpenFiles, limits(512, 4096)),
+            Ok(())
+        );
+        assert_eq!(
+            mock::table(),
+            vec![(Resource::OpenFiles, limits(512, 4096))]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn collect_reports_every_failure() {
+        mock::set_table(&[
+            (Resource::OpenFiles, limits(1024, 4096)),
+            (Resource::StackSize, limits(8, 16)),
+        ]);
+        assert_eq!(
+            set_all_resource_limits_collect(&[
+                (Resource::OpenFiles, limits(512, 4096)),
+                (Resource::StackSize, limits(8, 32)),
+                (Resource::CPUTime, limits(60, 120)),
+            ]),
+            Err(BatchError {
+                failures: vec![
+                    (Resource::StackSize, SetRLimitError::Permission),
+                    (Resource::CPUTime, SetRLimitError::Invalid),
+                ]
+            })
+        );
+        assert_eq!(
+            mock::table(),
+            vec![
+                (Resource::OpenFiles, limits(512, 4096)),
+                (Resource::StackSize, limits(8, 16)),
+            ]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn raising_hard_needs_privileges() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        assert_eq!(
+            set_soft_raising_hard_if_needed(Resource::OpenFiles, ResourceLimit::Value(8192)),
+            Err(SetRLimitError::Permission)
+        );
+        assert_eq!(
+            mock::table(),
+            vec![(Resource::OpenFiles, limits(1024, 4096))]
+        );
+        assert_eq!(
+            set_soft_raising_hard_if_needed(Resource::OpenFiles, ResourceLimit::Value(2048)),
+            Ok(limits(2048, 4096))
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn tunable_resources_have_room_to_raise() {
+        mock::set_table(&[
+            (
+                Resource::DataSize,
+                ResourceLimits::from_options((Some(10), None)),
+            ),
+            (Resource::OpenFiles, limits(1024, 4096)),
+            (Resource::StackSize, limits(16, 16)),
+        ]);
+        assert_eq!(
+            tunable_resources(),
+            vec![Resource::DataSize, Resource::OpenFiles]
+        );
+    }
+
+    #[test]
+    fn select_fd_limit_is_capped() {
+        assert!(matches!(
+            effective_select_fd_limit(),
+            ResourceLimit::Value(n) if n <= FD_SETSIZE
+        ));
+        #[cfg(feature = "mock")]
+        {
+            mock::set_table(&[(Resource::OpenFiles, limits(FD_SETSIZE * 4, FD_SETSIZE * 4))]);
+            assert_eq!(
+                effective_select_fd_limit(),
+                ResourceLimit::Value(FD_SETSIZE)
+            );
+            mock::set_table(&[(Resource::OpenFiles, limits(100, FD_SETSIZE * 4))]);
+            assert_eq!(effective_select_fd_limit(), ResourceLimit::Value(100));
+        }
+    }
+
+    #[test]
+    fn fingerprint_ignores_order() {
+        let policy = [
+            (Resource::OpenFiles, limits(1024, 4096)),
+            (Resource::StackSize, limits(8, 16)),
+        ];
+        let reordered = [policy[1], policy[0]];
+        let changed = [policy[0], (Resource::StackSize, limits(8, 32))];
+        assert_eq!(policy_fingerprint(&policy), policy_fingerprint(&policy));
+        assert_eq!(policy_fingerprint(&policy), policy_fingerprint(&reordered));
+        assert_ne!(policy_fingerprint(&policy), policy_fingerprint(&changed));
+    }
+
+    #[test]
+    fn raw_os_error_gives_the_errno() {
+        assert_eq!(GetRLimitError::Invalid.raw_os_error(), Some(libc::EINVAL));
+        assert_eq!(GetRLimitError::Permission.raw_os_error(), Some(libc::EPERM));
+        assert_eq!(SetRLimitError::Invalid.raw_os_error(), Some(libc::EINVAL));
+        assert_eq!(SetRLimitError::Permission.raw_os_error(), Some(libc::EPERM));
+    }
+
+    #[test]
+    fn memory_limit_delta_is_signed() {
+        assert_eq!(
+            memory_limit_delta(limits(100, 1000), limits(300, 1000)),
+            Some(200)
+        );
+        assert_eq!(
+            memory_limit_delta(limits(300, 1000), limits(100, 1000)),
+            Some(-200)
+        );
+        let unlimited = ResourceLimits::from_options((None, None));
+        assert_eq!(memory_limit_delta(limits(100, 1000), unlimited), None);
+        assert_eq!(memory_limit_delta(unlimited, limits(100, 1000)), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn filtered_limits_omit_unsupported_resources() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        assert_eq!(
+            get_all_resource_limits_filtered(true),
+            vec![(Resource::OpenFiles, Ok(limits(1024, 4096)))]
+        );
+        let all = get_all_resource_limits_filtered(false);
+        assert_eq!(all.len(), Resource::all().len());
+        assert!(all.contains(&(Resource::StackSize, Err(GetRLimitError::Invalid))));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn audit_limits_accepts_normal_limits() {
+        mock::set_table(&[
+            (Resource::OpenFiles, limits(1024, 4096)),
+            (
+                Resource::StackSize,
+                ResourceLimits::from_options((Some(8), None)),
+            ),
+        ]);
+        assert_eq!(audit_limits(), vec![]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn cpu_limit_minutes_round_trips() {
+        let unlimited = ResourceLimits::from_options((None, None));
+        mock::set_table(&[(Resource::CPUTime, unlimited)]);
+        assert_eq!(cpu_limit_minutes(), Ok(None));
+        assert_eq!(set_cpu_limit_minutes(u64::MAX), Ok(unlimited));
+        assert_eq!(set_cpu_limit_minutes(2), Ok(limits(120, 120)));
+        assert_eq!(cpu_limit_minutes(), Ok(Some(2)));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn cpu_policy_round_trips() {
+        let unlimited = ResourceLimits::from_options((None, None));
+        mock::set_table(&[(Resource::CPUTime, unlimited)]);
+        let inverted = CpuLimitPolicy {
+            soft_secs: Some(120),
+            hard_secs: Some(60),
+        };
+        assert_eq!(set_cpu_policy(inverted), Err(SetRLimitError::Invalid));
+        assert_eq!(mock::table(), vec![(Resource::CPUTime, unlimited)]);
+        for policy in [
+            CpuLimitPolicy {
+                soft_secs: Some(60),
+                hard_secs: None,
+            },
+            CpuLimitPolicy {
+                soft_secs: Some(60),
+                hard_secs: Some(120),
+            },
+        ] {
+            assert_eq!(set_cpu_policy(policy), Ok(()));
+            assert_eq!(cpu_policy(), Ok(policy));
         }
     }
+
+    #[test]
+    fn distinct_resources_have_distinct_codes() {
+        assert!(Resource::same_kernel_code(
+            Resource::OpenFiles,
+            Resource::OpenFiles
+        ));
+        assert!(!Resource::same_kernel_code(
+            Resource::OpenFiles,
+            Resource::StackSize
+        ));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn ratchet_only_tightens() {
+        // RATCHET is shared by every test, so no other test may ratchet
+        // DataSize.
+        mock::set_table(&[(Resource::DataSize, limits(1000, 2000))]);
+        assert_eq!(
+            ratchet_soft_limit(Resource::DataSize, 500),
+            Ok(limits(500, 2000))
+        );
+        assert_eq!(
+            ratchet_soft_limit(Resource::DataSize, 800),
+            Ok(limits(500, 2000))
+        );
+        assert_eq!(mock::table(), vec![(Resource::DataSize, limits(500, 2000))]);
+        assert_eq!(
+            ratchet_soft_limit(Resource::DataSize, 300),
+            Ok(limits(300, 2000))
+        );
+    }
+
+    #[test]
+    fn apply_required_checks_before_setting() {
+        #[cfg(feature = "mock")]
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        assert_eq!(
+            apply_required(
+                &[Resource::OpenFiles, Resource::StackSize],
+                &[(Resource::OpenFiles, limits(512, 4096))],
+            ),
+            Err((Resource::StackSize, SetRLimitError::Invalid))
+        );
+        #[cfg(feature = "mock")]
+        assert_eq!(
+            mock::table(),
+            vec![(Resource::OpenFiles, limits(1024, 4096))]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn describe_resource_shows_both_limits() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        assert_eq!(
+            describe_resource(Resource::OpenFiles).unwrap(),
+            "nofile: soft=1024 hard=4096 (count)"
+        );
+    }
+
+    #[test]
+    fn memory_limits_must_fit_in_the_address_space() {
+        let unlimited = ResourceLimits::from_options((None, None));
+        assert!(memory_limits_consistent(
+            limits(100, 100),
+            limits(40, 40),
+            limits(50, 50)
+        ));
+        assert!(!memory_limits_consistent(
+            limits(100, 100),
+            limits(60, 60),
+            limits(50, 50)
+        ));
+        assert!(memory_limits_consistent(unlimited, unlimited, unlimited));
+        assert!(!memory_limits_consistent(
+            limits(100, 100),
+            unlimited,
+            limits(50, 50)
+        ));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn set_memory_limits_rolls_back() {
+        let initial = [
+            (Resource::DataSize, limits(100, 100)),
+            (Resource::StackSize, limits(100, 100)),
+            (Resource::TotalMemory, limits(1000, 1000)),
+        ];
+        mock::set_table(&initial);
+        assert_eq!(
+            set_memory_limits(limits(100, 1000), limits(100, 100), limits(100, 100)),
+            Err(MemoryLimitError::Inconsistent)
+        );
+        assert_eq!(mock::table(), initial);
+        assert_eq!(
+            set_memory_limits(limits(500, 1000), limits(100, 100), limits(100, 200)),
+            Err(MemoryLimitError::Set(
+                Resource::StackSize,
+                SetRLimitError::Permission
+            ))
+        );
+        assert_eq!(mock::table(), initial);
+        assert_eq!(
+            set_memory_limits(limits(500, 1000), limits(100, 100), limits(100, 100)),
+            Ok(())
+        );
+        assert_eq!(mock::table()[2], (Resource::TotalMemory, limits(500, 1000)));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn converged_limits_plan_no_changes() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        assert_eq!(
+            plan_changes(&[(Resource::OpenFiles, limits(1024, 4096))]),
+            vec![]
+        );
+        assert_eq!(
+            plan_changes(&[(Resource::OpenFiles, limits(512, 4096))]),
+            vec![LimitChange {
+                resource: Resource::OpenFiles,
+                from: limits(1024, 4096),
+                to: limits(512, 4096),
+            }]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn wide_open_limits_are_not_restricted() {
+        let unlimited = ResourceLimits::from_options((None, None));
+        mock::set_table(&[
+            (Resource::CPUTime, unlimited),
+            (Resource::DataSize, unlimited),
+            (Resource::OpenFiles, limits(1024, 4096)),
+            (Resource::TotalMemory, unlimited),
+        ]);
+        assert!(!looks_restricted());
+        mock::set_table(&[(Resource::OpenFiles, limits(64, 4096))]);
+        assert!(looks_restricted());
+    }
+
+    #[test]
+    fn raw_pair_matches_the_limits() {
+        let (code, rlimit) = to_raw_pair(Resource::OpenFiles, limits(1024, 4096));
+        assert_eq!(code, libc::RLIMIT_NOFILE);
+        assert_eq!((rlimit.rlim_cur, rlimit.rlim_max), (1024, 4096));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn partial_set_keeps_the_hard_limit() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        assert_eq!(
+            set_resource_limit_partial(
+                Resource::OpenFiles,
+                ResourceLimit::Value(2048).into(),
+                MaybeLimit::Keep,
+            ),
+            Ok(limits(2048, 4096))
+        );
+        assert_eq!(
+            mock::table(),
+            vec![(Resource::OpenFiles, limits(2048, 4096))]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn safe_max_open_files_caps_for_select() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, FD_SETSIZE * 4))]);
+        assert_eq!(
+            safe_max_open_files(true),
+            Ok(ResourceLimit::Value(FD_SETSIZE))
+        );
+        assert_eq!(
+            safe_max_open_files(false),
+            Ok(ResourceLimit::Value(FD_SETSIZE * 4))
+        );
+        mock::set_table(&[(
+            Resource::OpenFiles,
+            ResourceLimits::from_options((Some(1024), None)),
+        )]);
+        assert_eq!(
+            safe_max_open_files(true),
+            Ok(ResourceLimit::Value(FD_SETSIZE))
+        );
+        assert_eq!(safe_max_open_files(false), Ok(ResourceLimit::Infinity));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn limits_array_has_an_entry_per_resource() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        let array = all_limits_array();
+        assert_eq!(array.len(), Resource::all().len());
+        assert!(array.contains(&(libc::RLIMIT_NOFILE as i32, 1024, 4096)));
+        assert!(array.contains(&(
+            libc::RLIMIT_STACK as i32,
+            libc::RLIM_SAVED_CUR,
+            libc::RLIM_SAVED_MAX
+        )));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn report_shows_unsupported_resources() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        let report = report_string();
+        let line = |name: &str| {
+            report
+                .lines()
+                .find(|line| line.split_whitespace().next() == Some(name))
+                .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        };
+        assert_eq!(line("nofile"), Some(vec!["nofile", "1024", "4096"]));
+        assert_eq!(
+            line("stack"),
+            Some(vec!["stack", "unsupported", "unsupported"])
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn per_worker_limits_divides_shareable_limits() {
+        mock::set_table(&[
+            (Resource::CPUTime, limits(60, 120)),
+            (Resource::OpenFiles, limits(1000, 4000)),
+        ]);
+        assert_eq!(per_worker_limits(0), Err(GetRLimitError::Invalid));
+        let limits_of = |resource| {
+            per_worker_limits(4)
+                .unwrap()
+                .into_iter()
+                .find(|&(r, _)| r == resource)
+                .map(|(_, limits)| limits)
+        };
+        assert_eq!(limits_of(Resource::OpenFiles), Some(Ok(limits(250, 1000))));
+        assert_eq!(limits_of(Resource::CPUTime), Some(Ok(limits(60, 120))));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn apply_and_report_records_previous_limits() {
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        assert_eq!(
+            apply_and_report(&[
+                (Resource::OpenFiles, limits(512, 4096)),
+                (Resource::StackSize, limits(8, 16)),
+            ]),
+            vec![
+                ChangeReport {
+                    resource: Resource::OpenFiles,
+                    before: Ok(limits(1024, 4096)),
+                    after: limits(512, 4096),
+                    result: Ok(()),
+                },
+                ChangeReport {
+                    resource: Resource::StackSize,
+                    before: Err(GetRLimitError::Invalid),
+                    after: limits(8, 16),
+                    result: Err(SetRLimitError::Invalid),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn stack_limit_does_not_affect_the_current_process() {
+        assert!(!Resource::StackSize.set_affects_current_process());
+        assert!(Resource::OpenFiles.set_affects_current_process());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn clamp_warning_compares_the_stored_limits() {
+        // The mock never clamps, so compare against limits other than those
+        // stored, as if the kernel had changed them.
+        mock::set_table(&[(Resource::OpenFiles, limits(512, 4096))]);
+        set_clamp_warning(true);
+        let clamped = clamp_warning(Resource::OpenFiles, limits(1024, 4096));
+        let stored = clamp_warning(Resource::OpenFiles, limits(512, 4096));
+        set_clamp_warning(false);
+        assert_eq!(
+            clamped.as_deref(),
+            Some(
+                "warning: nofile limits were stored as soft 512 hard 4096 instead of the \
+                 requested soft 1024 hard 4096"
+            )
+        );
+        assert_eq!(stored, None);
+        assert_eq!(clamp_warning(Resource::OpenFiles, limits(1024, 4096)), None);
+    }
 }
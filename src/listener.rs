@@ -0,0 +1,63 @@
+use crate::{Resource, ResourceLimits};
+
+type Listener = std::sync::Arc<dyn Fn(Resource, ResourceLimits) + Send + Sync>;
+
+/// Callbacks registered with [`on_limit_changed`].
+static LISTENERS: std::sync::Mutex<Vec<Listener>> = std::sync::Mutex::new(Vec::new());
+
+/// Register a callback which is called with the resource and the new limits
+/// after every successful change to the limits of the current process made
+/// through this crate (e.g. by [`set_resource_limit`](crate::set_resource_limit)).
+/// Changes made by other means are not reported. Callbacks cannot be
+/// unregistered.
+///
+/// Callbacks are called on the thread that changed the limit, and may
+/// themselves change limits.
+pub fn on_limit_changed(callback: impl Fn(Resource, ResourceLimits) + Send + Sync + 'static) {
+    LISTENERS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .push(std::sync::Arc::new(callback));
+}
+
+/// Call every registered callback. The registry is not locked while the
+/// callbacks run, so that they may register callbacks or change limits.
+pub(crate) fn notify_limit_changed(resource: Resource, limits: ResourceLimits) {
+    let listeners = LISTENERS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    for listener in listeners {
+        listener(resource, limits);
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::{limits, mock, set_resource_limit, SetRLimitError};
+
+    thread_local! {
+        static CHANGES: std::cell::RefCell<Vec<(Resource, ResourceLimits)>> =
+            const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    #[test]
+    fn listener_sees_each_successful_change() {
+        // Callbacks run on the thread that changed the limit, so changes made
+        // by other tests are recorded on their own threads.
+        on_limit_changed(|resource, limits| {
+            CHANGES.with(|changes| changes.borrow_mut().push((resource, limits)))
+        });
+        mock::set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        set_resource_limit(Resource::OpenFiles, limits(512, 4096)).unwrap();
+        assert_eq!(
+            set_resource_limit(Resource::OpenFiles, limits(512, 8192)),
+            Err(SetRLimitError::Permission)
+        );
+        assert_eq!(
+            CHANGES.with(|changes| changes.take()),
+            [(Resource::OpenFiles, limits(512, 4096))]
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! An in-memory stand-in for getrlimit and setrlimit, enabled by the `mock`
+//! feature, so that code built on this crate can be tested without changing
+//! the limits of the real process or needing privileges.
+//!
+//! Each thread has its own table, which starts empty. Resources not in the
+//! table are rejected with EINVAL, as if the platform did not support them.
+//! Setting limits behaves like an unprivileged process: a soft limit above
+//! the hard limit is rejected with EINVAL, and raising a hard limit with
+//! EPERM.
+//!
+//! Only the getrlimit and setrlimit calls made by this crate are replaced.
+//! prlimit, getrusage, and limits set in child processes (e.g. by
+//! [`spawn_with_limits`](crate::spawn_with_limits)) still use the real
+//! syscalls. Results which this crate would otherwise cache for the whole
+//! process, such as [`Resource::is_supported`], are not cached with this
+//! feature, so each thread only ever sees its own table. The exception is the
+//! thread started by [`enforce_with_watchdog`](crate::enforce_with_watchdog),
+//! which starts with a copy of the table of the thread that started it.
+
+use crate::{Resource, ResourceLimits};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+thread_local! {
+    static TABLE: RefCell<BTreeMap<libc::__rlimit_resource_t, libc::rlimit>> =
+        const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Replace the calling thread's table with the given limits. The limits are
+/// stored as given, even if they are inconsistent.
+pub fn set_table(limits: &[(Resource, ResourceLimits)]) {
+    TABLE.with(|table| {
+        *table.borrow_mut() = limits
+            .iter()
+            .map(|&(resource, limits)| (resource.into(), limits.into()))
+            .collect();
+    });
+}
+
+/// Get the contents of the calling thread's table, in the canonical resource
+/// order.
+pub fn table() -> Vec<(Resource, ResourceLimits)> {
+    let mut limits: Vec<(Resource, ResourceLimits)> = TABLE.with(|table| {
+        table
+            .borrow()
+            .iter()
+            .map(|(&resource, &rlimit)| (resource.into(), rlimit.into()))
+            .collect()
+    });
+    limits.sort();
+    limits
+}
+
+pub(crate) fn getrlimit(
+    resource: libc::__rlimit_resource_t,
+    rlimit: &mut libc::rlimit,
+) -> Result<(), i32> {
+    TABLE.with(|table| match table.borrow().get(&resource) {
+        Some(stored) => {
+            *rlimit = *stored;
+            Ok(())
+        }
+        None => Err(libc::EINVAL),
+    })
+}
+
+pub(crate) fn setrlimit(
+    resource: libc::__rlimit_resource_t,
+    rlimit: &libc::rlimit,
+) -> Result<(), i32> {
+    TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        let stored = table.get_mut(&resource).ok_or(libc::EINVAL)?;
+        if rlimit.rlim_cur > rlimit.rlim_max {
+            return Err(libc::EINVAL);
+        }
+        if rlimit.rlim_max > stored.rlim_max {
+            return Err(libc::EPERM);
+        }
+        *stored = *rlimit;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_resource_limit, limits, raise_soft_to_hard, GetRLimitError};
+
+    #[test]
+    fn helpers_use_the_table() {
+        set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        assert_eq!(
+            raise_soft_to_hard(Resource::OpenFiles),
+            Ok(limits(4096, 4096))
+        );
+        assert_eq!(table(), vec![(Resource::OpenFiles, limits(4096, 4096))]);
+        assert_eq!(
+            get_resource_limit(Resource::StackSize),
+            Err(GetRLimitError::Invalid)
+        );
+    }
+
+    #[test]
+    fn support_follows_the_table() {
+        set_table(&[]);
+        assert!(!Resource::OpenFiles.is_supported());
+        set_table(&[(Resource::OpenFiles, limits(1024, 4096))]);
+        assert!(Resource::OpenFiles.is_supported());
+    }
+}
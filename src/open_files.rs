@@ -0,0 +1,85 @@
+//! A typed interface to the [`Resource::OpenFiles`] limit, the maximum number
+//! of file descriptors the process may have open.
+
+use crate::{
+    get_resource_limit, raise_soft_to_hard, set_soft_limit, GetRLimitError, Resource,
+    ResourceLimit, ResourceLimits, SetRLimitError,
+};
+
+/// The open files limits, as returned by [`get`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct OpenFilesLimit(pub ResourceLimits);
+
+impl OpenFilesLimit {
+    /// The number of files which may be open before opening another fails,
+    /// or `None` if this is unlimited or unknown.
+    pub fn soft(&self) -> Option<libc::rlim_t> {
+        self.0.as_options().0
+    }
+
+    /// The highest value the soft limit may be raised to without privileges,
+    /// or `None` if this is unlimited or unknown.
+    pub fn hard(&self) -> Option<libc::rlim_t> {
+        self.0.as_options().1
+    }
+
+    /// The underlying limit values.
+    pub fn limits(&self) -> ResourceLimits {
+        self.0
+    }
+}
+
+impl From<OpenFilesLimit> for ResourceLimits {
+    fn from(limit: OpenFilesLimit) -> Self {
+        limit.0
+    }
+}
+
+/// Get the open files limits of the current process.
+pub fn get() -> Result<OpenFilesLimit, GetRLimitError> {
+    get_resource_limit(Resource::OpenFiles).map(OpenFilesLimit)
+}
+
+/// Set the soft open files limit to `n`, keeping the current hard limit.
+/// Returns the new limits.
+pub fn set_soft(n: libc::rlim_t) -> Result<OpenFilesLimit, SetRLimitError> {
+    set_soft_limit(Resource::OpenFiles, ResourceLimit::Value(n)).map(OpenFilesLimit)
+}
+
+/// Raise the soft open files limit to the hard limit, returning the new
+/// limits.
+pub fn maximize() -> Result<OpenFilesLimit, SetRLimitError> {
+    raise_soft_to_hard(Resource::OpenFiles).map(OpenFilesLimit)
+}
+
+/// Get the number of file descriptors currently open in this process. See
+/// [`open_file_count`](crate::open_file_count).
+#[cfg(target_os = "linux")]
+pub fn current_count() -> std::io::Result<u64> {
+    crate::open_file_count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn helpers_use_the_open_files_limit() {
+        crate::mock::set_table(&[(Resource::OpenFiles, crate::limits(1024, 4096))]);
+        let limit = get().unwrap();
+        assert_eq!((limit.soft(), limit.hard()), (Some(1024), Some(4096)));
+        assert_eq!(set_soft(512), Ok(OpenFilesLimit(crate::limits(512, 4096))));
+        assert_eq!(maximize(), Ok(OpenFilesLimit(crate::limits(4096, 4096))));
+        assert_eq!(
+            get().map(|limit| limit.limits()),
+            Ok(crate::limits(4096, 4096))
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn counts_the_standard_streams() {
+        assert!(current_count().unwrap() >= 3);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{
+    apply_transactional, validate_policy, PolicyIssue, Resource, ResourceLimits, SetRLimitError,
+    Snapshot,
+};
+
+/// A set of limits to apply together, with at most one entry per resource.
+/// Create one with [`Policy::builder`].
+///
+/// With the `serde` feature a policy is serialized as a list of
+/// `(resource, limits)` pairs, and deserializing one with a duplicated
+/// resource fails.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        try_from = "Vec<(Resource, ResourceLimits)>",
+        into = "Vec<(Resource, ResourceLimits)>"
+    )
+)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Policy {
+    limits: Vec<(Resource, ResourceLimits)>,
+}
+
+/// A builder for [`Policy`], created by [`Policy::builder`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PolicyBuilder {
+    limits: Vec<(Resource, ResourceLimits)>,
+}
+
+impl PolicyBuilder {
+    /// Add the limits of a resource.
+    pub fn limit(mut self, resource: Resource, limits: ResourceLimits) -> Self {
+        self.limits.push((resource, limits));
+        self
+    }
+
+    /// Build the policy, failing with [`PolicyIssue::Duplicate`] if a resource
+    /// was added more than once.
+    pub fn build(self) -> Result<Policy, PolicyIssue> {
+        Policy::try_from(self.limits)
+    }
+}
+
+impl Policy {
+    /// Start building a policy.
+    pub fn builder() -> PolicyBuilder {
+        PolicyBuilder::default()
+    }
+
+    /// The limits in the policy, in the order they were added.
+    pub fn limits(&self) -> &[(Resource, ResourceLimits)] {
+        &self.limits
+    }
+
+    /// Check the limits for problems without changing any, as
+    /// [`validate_policy`] does.
+    pub fn validate(&self) -> Result<(), Vec<PolicyIssue>> {
+        validate_policy(&self.limits)
+    }
+
+    /// Set every limit in the policy, in the order they were added.
+    ///
+    /// If setting any of the limits fails, those already set are restored to
+    /// their previous values and the failing resource is returned with the
+    /// error. Restoring a hard limit which was lowered requires privileges, so
+    /// without them the rollback may be incomplete.
+    pub fn apply(&self) -> Result<(), (Resource, SetRLimitError)> {
+        apply_transactional(&self.limits)
+    }
+
+    /// Convert the policy into a [`Snapshot`] of the same limits.
+    pub fn to_snapshot(&self) -> Snapshot {
+        Snapshot::new(self.limits.clone())
+    }
+}
+
+impl TryFrom<Vec<(Resource, ResourceLimits)>> for Policy {
+    type Error = PolicyIssue;
+
+    /// Create a policy from a list of limits, failing with
+    /// [`PolicyIssue::Duplicate`] if a resource appears more than once.
+    fn try_from(limits: Vec<(Resource, ResourceLimits)>) -> Result<Self, Self::Error> {
+        let mut seen = std::collections::BTreeSet::new();
+        for &(resource, _) in &limits {
+            if !seen.insert(resource) {
+                return Err(PolicyIssue::Duplicate(resource));
+            }
+        }
+        Ok(Policy { limits })
+    }
+}
+
+impl From<Policy> for Vec<(Resource, ResourceLimits)> {
+    fn from(policy: Policy) -> Self {
+        policy.limits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits;
+
+    #[test]
+    fn builder_rejects_duplicates() {
+        let policy = Policy::builder()
+            .limit(Resource::OpenFiles, limits(10, 100))
+            .limit(Resource::StackSize, limits(20, 200))
+            .build()
+            .unwrap();
+        assert_eq!(
+            policy.limits(),
+            &[
+                (Resource::OpenFiles, limits(10, 100)),
+                (Resource::StackSize, limits(20, 200)),
+            ]
+        );
+        assert_eq!(policy.to_snapshot().limits(), policy.limits());
+        assert_eq!(
+            Policy::builder()
+                .limit(Resource::OpenFiles, limits(10, 100))
+                .limit(Resource::OpenFiles, limits(20, 100))
+                .build(),
+            Err(PolicyIssue::Duplicate(Resource::OpenFiles))
+        );
+    }
+
+    #[test]
+    fn validate_reports_issues() {
+        let policy = Policy::builder()
+            .limit(Resource::OpenFiles, limits(200, 100))
+            .build()
+            .unwrap();
+        assert_eq!(
+            policy.validate(),
+            Err(vec![PolicyIssue::SoftExceedsHard(Resource::OpenFiles)])
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn apply_rolls_back_on_failure() {
+        let initial = [
+            (Resource::OpenFiles, limits(1024, 4096)),
+            (Resource::StackSize, limits(8, 16)),
+        ];
+        crate::mock::set_table(&initial);
+        let policy = Policy::builder()
+            .limit(Resource::OpenFiles, limits(512, 4096))
+            .limit(Resource::StackSize, limits(8, 32))
+            .build()
+            .unwrap();
+        assert_eq!(
+            policy.apply(),
+            Err((Resource::StackSize, SetRLimitError::Permission))
+        );
+        assert_eq!(crate::mock::table(), initial);
+
+        // A lowered hard limit cannot be raised again without privileges.
+        let policy = Policy::builder()
+            .limit(Resource::OpenFiles, limits(512, 2048))
+            .limit(Resource::StackSize, limits(8, 32))
+            .build()
+            .unwrap();
+        assert!(policy.apply().is_err());
+        assert_eq!(
+            crate::mock::table(),
+            vec![
+                (Resource::OpenFiles, limits(512, 2048)),
+                (Resource::StackSize, limits(8, 16)),
+            ]
+        );
+        crate::mock::set_table(&initial);
+
+        let policy = Policy::builder()
+            .limit(Resource::OpenFiles, limits(512, 4096))
+            .build()
+            .unwrap();
+        assert_eq!(policy.apply(), Ok(()));
+        assert_eq!(
+            crate::mock::table(),
+            vec![
+                (Resource::OpenFiles, limits(512, 4096)),
+                (Resource::StackSize, limits(8, 16)),
+            ]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trips() {
+        let policy = Policy::builder()
+            .limit(Resource::OpenFiles, limits(10, 100))
+            .build()
+            .unwrap();
+        let json = serde_json::to_string(&policy).unwrap();
+        assert_eq!(serde_json::from_str::<Policy>(&json).unwrap(), policy);
+
+        let duplicated = serde_json::to_string(&vec![
+            (Resource::OpenFiles, limits(10, 100)),
+            (Resource::OpenFiles, limits(20, 100)),
+        ])
+        .unwrap();
+        assert!(serde_json::from_str::<Policy>(&duplicated).is_err());
+    }
+}
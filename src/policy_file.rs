@@ -0,0 +1,127 @@
+//! Loading limits from a TOML policy file, enabled with the `toml` feature.
+//!
+//! A policy file has a `[limits]` table keyed by resource name:
+//!
+//! ```toml
+//! [limits]
+//! nofile = { soft = 1024, hard = 4096 }
+//! core = { soft = 0, hard = "unlimited" }
+//! ```
+
+use crate::{
+    set_resource_limit, ParseResourceError, Resource, ResourceLimit, ResourceLimits, SetRLimitError,
+};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// An error value returned from the failure of [`apply_policy_file`].
+#[derive(Debug)]
+pub enum PolicyError {
+    /// The policy file could not be read.
+    Io(std::io::Error),
+    /// The policy file is not valid TOML or does not have the expected shape.
+    Parse(toml::de::Error),
+    /// A key in the `[limits]` table is not a resource name.
+    UnknownResource(ParseResourceError),
+    /// The limits of a resource could not be set. Resources before it in the
+    /// canonical order have already been set.
+    Set(Resource, SetRLimitError),
+}
+
+impl std::fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "could not read policy: {}", err),
+            Self::Parse(err) => write!(f, "could not parse policy: {}", err),
+            Self::UnknownResource(err) => err.fmt(f),
+            Self::Set(resource, err) => write!(f, "could not set {} limit: {}", resource, err),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Parse(err) => Some(err),
+            Self::UnknownResource(err) => Some(err),
+            Self::Set(_, err) => Some(err),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct PolicyFile {
+    limits: BTreeMap<String, PolicyEntry>,
+}
+
+#[derive(serde::Deserialize)]
+struct PolicyEntry {
+    soft: ResourceLimit,
+    hard: ResourceLimit,
+}
+
+/// Parse the limits described by a TOML policy, sorted in the canonical
+/// resource order.
+fn parse_policy(text: &str) -> Result<Vec<(Resource, ResourceLimits)>, PolicyError> {
+    let policy: PolicyFile = toml::from_str(text).map_err(PolicyError::Parse)?;
+    let mut limits = policy
+        .limits
+        .into_iter()
+        .map(|(name, entry)| {
+            let resource = name.parse().map_err(PolicyError::UnknownResource)?;
+            Ok((
+                resource,
+                ResourceLimits {
+                    soft_limit: entry.soft,
+                    hard_limit: entry.hard,
+                },
+            ))
+        })
+        .collect::<Result<Vec<_>, PolicyError>>()?;
+    limits.sort_by_key(|&(resource, _)| resource);
+    Ok(limits)
+}
+
+/// Read a TOML policy file and apply the limits it describes to the current
+/// process, returning the limits that were applied. Resources are set in the
+/// canonical order, and setting stops at the first failure.
+pub fn apply_policy_file(path: &Path) -> Result<Vec<(Resource, ResourceLimits)>, PolicyError> {
+    let text = std::fs::read_to_string(path).map_err(PolicyError::Io)?;
+    let limits = parse_policy(&text)?;
+    for &(resource, resource_limits) in &limits {
+        set_resource_limit(resource, resource_limits)
+            .map_err(|err| PolicyError::Set(resource, err))?;
+    }
+    Ok(limits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits;
+
+    #[test]
+    fn parses_the_documented_example() {
+        let text = "[limits]\n\
+                    nofile = { soft = 1024, hard = 4096 }\n\
+                    core = { soft = 0, hard = \"unlimited\" }\n";
+        assert_eq!(
+            parse_policy(text).unwrap(),
+            [
+                (
+                    Resource::CoreFileSize,
+                    ResourceLimits::from_options((Some(0), None))
+                ),
+                (Resource::OpenFiles, limits(1024, 4096)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_resources() {
+        let err = parse_policy("[limits]\nfiles = { soft = 1, hard = 2 }\n").unwrap_err();
+        assert!(matches!(err, PolicyError::UnknownResource(_)));
+        assert!(err.to_string().contains("files"));
+    }
+}
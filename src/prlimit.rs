@@ -0,0 +1,380 @@
+use crate::{
+    check_syscall, get_resource_limit, invalidate_soft_limit_cache, notify_limit_changed,
+    set_resource_limit, GetRLimitError, Resource, ResourceLimit, ResourceLimits, SetRLimitError,
+};
+
+/// An error value returned from the failure of [`prlimit`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum PrLimitError {
+    /// [EINVAL] An invalid resource was specified; or the new rlim_cur exceeds
+    /// the new rlim_max.
+    Invalid,
+    /// [EPERM] The calling process does not have permission to set limits for
+    /// the target process, or tried to raise a hard limit without appropriate
+    /// privileges.
+    Permission,
+    /// [ESRCH] Could not find a process with the given ID.
+    NoSuchProcess,
+    /// [ENOSYS] The kernel does not provide prlimit (it was added in Linux
+    /// 2.6.36) and the target is not the current process.
+    Unsupported,
+}
+
+impl std::fmt::Display for PrLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Invalid => write!(f, "EINVAL"),
+            Self::Permission => write!(f, "EPERM"),
+            Self::NoSuchProcess => write!(f, "ESRCH"),
+            Self::Unsupported => write!(f, "ENOSYS"),
+        }
+    }
+}
+
+impl std::error::Error for PrLimitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+}
+
+impl From<GetRLimitError> for PrLimitError {
+    fn from(err: GetRLimitError) -> Self {
+        match err {
+            GetRLimitError::Invalid => PrLimitError::Invalid,
+            GetRLimitError::Permission => PrLimitError::Permission,
+        }
+    }
+}
+
+impl From<SetRLimitError> for PrLimitError {
+    fn from(err: SetRLimitError) -> Self {
+        match err {
+            SetRLimitError::Invalid => PrLimitError::Invalid,
+            SetRLimitError::Permission => PrLimitError::Permission,
+        }
+    }
+}
+
+/// Returns true if `pid` refers to the calling process.
+fn is_current_process(pid: libc::pid_t) -> bool {
+    pid == 0 || pid == unsafe { libc::getpid() }
+}
+
+/// Get, and optionally set, the limit values for a particular resource of the
+/// process `pid`. A `pid` of 0 refers to the current process. The returned
+/// value is the limit as it was before any new limit was applied.
+///
+/// On kernels older than 2.6.36 prlimit is not available. In that case, if
+/// `pid` refers to the current process, this falls back to getrlimit and
+/// setrlimit (which are not applied atomically). For any other process
+/// [`PrLimitError::Unsupported`] is returned.
+pub fn prlimit(
+    pid: libc::pid_t,
+    resource: Resource,
+    new_limit: Option<ResourceLimits>,
+) -> Result<ResourceLimits, PrLimitError> {
+    let mut old_rlimit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    with_fallback(
+        pid,
+        call_prlimit(pid, resource, new_limit, &mut old_rlimit).map(|()| old_rlimit.into()),
+        || prlimit_fallback(resource, new_limit),
+    )
+}
+
+/// Get the limit values for a particular resource of the process `pid`,
+/// without setting anything. See [`prlimit`].
+pub fn prlimit_get(pid: libc::pid_t, resource: Resource) -> Result<ResourceLimits, PrLimitError> {
+    prlimit(pid, resource, None)
+}
+
+/// Set the limit values for a particular resource of the process `pid`,
+/// without reading the previous values. See [`prlimit`].
+pub fn prlimit_set(
+    pid: libc::pid_t,
+    resource: Resource,
+    limits: ResourceLimits,
+) -> Result<(), PrLimitError> {
+    with_fallback(
+        pid,
+        call_prlimit(pid, resource, Some(limits), std::ptr::null_mut()),
+        || prlimit_fallback(resource, Some(limits)).map(|_| ()),
+    )
+}
+
+/// Handle the result of the prlimit syscall for the process `pid`. If the
+/// kernel lacks prlimit and `pid` refers to the current process, the result
+/// of `fallback` is used instead; otherwise the result is returned as is.
+fn with_fallback<T>(
+    pid: libc::pid_t,
+    result: Result<T, PrLimitError>,
+    fallback: impl FnOnce() -> Result<T, PrLimitError>,
+) -> Result<T, PrLimitError> {
+    match result {
+        Err(PrLimitError::Unsupported) if is_current_process(pid) => fallback(),
+        result => result,
+    }
+}
+
+/// Call the prlimit syscall, where `old_rlimit` may be null. ENOSYS is
+/// returned as [`PrLimitError::Unsupported`] without trying a fallback.
+fn call_prlimit(
+    pid: libc::pid_t,
+    resource: Resource,
+    new_limit: Option<ResourceLimits>,
+    old_rlimit: *mut libc::rlimit,
+) -> Result<(), PrLimitError> {
+    let new_rlimit: Option<libc::rlimit> = new_limit.map(|limits| limits.into());
+    let new_ptr = match &new_rlimit {
+        Some(rlimit) => rlimit as *const libc::rlimit,
+        None => std::ptr::null(),
+    };
+    match check_syscall(unsafe { libc::prlimit(pid, resource.into(), new_ptr, old_rlimit) }) {
+        Ok(()) => {
+            if let Some(new_limit) = new_limit {
+                if is_current_process(pid) {
+                    invalidate_soft_limit_cache();
+                    notify_limit_changed(resource, new_limit);
+                }
+            }
+            Ok(())
+        }
+        Err(libc::EINVAL) => Err(PrLimitError::Invalid),
+        Err(libc::EPERM) => Err(PrLimitError::Permission),
+        Err(libc::ESRCH) => Err(PrLimitError::NoSuchProcess),
+        Err(libc::ENOSYS) => Err(PrLimitError::Unsupported),
+        Err(_) => panic!("Invalid error code"),
+    }
+}
+
+/// Emulate [`prlimit`] for the current process with getrlimit and setrlimit,
+/// for kernels that lack the prlimit syscall.
+fn prlimit_fallback(
+    resource: Resource,
+    new_limit: Option<ResourceLimits>,
+) -> Result<ResourceLimits, PrLimitError> {
+    let old_limit = get_resource_limit(resource)?;
+    if let Some(new_limit) = new_limit {
+        set_resource_limit(resource, new_limit)?;
+    }
+    Ok(old_limit)
+}
+
+/// Copy the limits of every resource from the process `pid` to the current
+/// process. Resources whose limits cannot be read from `pid` are skipped. The
+/// first resource which fails to be set is returned along with the error.
+pub fn inherit_limits_from(pid: libc::pid_t) -> Result<(), (Resource, SetRLimitError)> {
+    for &resource in Resource::all() {
+        if let Ok(limits) = prlimit(pid, resource, None) {
+            set_resource_limit(resource, limits).map_err(|err| (resource, err))?;
+        }
+    }
+    Ok(())
+}
+
+/// Get the limits of every resource of the process `pid`, for example a
+/// spawned child. Resources whose limits cannot be read are omitted.
+pub fn child_limits(pid: libc::pid_t) -> Vec<(Resource, ResourceLimits)> {
+    Resource::all()
+        .iter()
+        .filter_map(|&resource| {
+            prlimit(pid, resource, None)
+                .ok()
+                .map(|limits| (resource, limits))
+        })
+        .collect()
+}
+
+/// Get the RLIMIT_RTTIME limits (the CPU time, in microseconds, a real-time
+/// thread may consume without blocking) of every thread of the current
+/// process, keyed by thread ID. Threads which exit while the list is being
+/// read are omitted.
+pub fn thread_rttime_limits() -> std::io::Result<Vec<(libc::pid_t, ResourceLimits)>> {
+    let mut limits = Vec::new();
+    for entry in std::fs::read_dir("/proc/self/task")? {
+        let tid = match entry?
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(tid) => tid,
+            None => continue,
+        };
+        let mut rlimit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        match unsafe { libc::prlimit(tid, libc::RLIMIT_RTTIME, std::ptr::null(), &mut rlimit) } {
+            0 => limits.push((tid, rlimit.into())),
+            _ => {
+                let err = std::io::Error::last_os_error();
+                if err.raw_os_error() != Some(libc::ESRCH) {
+                    return Err(err);
+                }
+            }
+        }
+    }
+    limits.sort_by_key(|&(tid, _)| tid);
+    Ok(limits)
+}
+
+/// Adopt the limits of the process `pid`, but never above the current hard
+/// limits of this process: each resource's hard limit is set to the lower of
+/// the two hard limits, and its soft limit to the lower of the reference soft
+/// limit and that new hard limit. This never requires privileges.
+///
+/// Resources whose limits cannot be read from `pid` are skipped. The first
+/// resource which fails to be set is returned along with the error.
+pub fn adopt_limits_capped(pid: libc::pid_t) -> Result<(), (Resource, SetRLimitError)> {
+    let min = |a: ResourceLimit, b: ResourceLimit| if a.exceeds(&b) { b } else { a };
+    for &resource in Resource::all() {
+        let reference = match prlimit(pid, resource, None) {
+            Ok(limits) => limits,
+            Err(_) => continue,
+        };
+        let own = get_resource_limit(resource).map_err(|err| (resource, err.into()))?;
+        let hard_limit = min(reference.hard_limit, own.hard_limit);
+        let limits = ResourceLimits {
+            soft_limit: min(reference.soft_limit, hard_limit),
+            hard_limit,
+        };
+        if limits != own {
+            set_resource_limit(resource, limits).map_err(|err| (resource, err))?;
+        }
+    }
+    Ok(())
+}
+
+/// Set the limit values for a particular resource of every process in the
+/// process group `pgid`, returning the IDs of the processes they were set for.
+/// Processes whose limits cannot be set (e.g. for lack of permission) are
+/// skipped. Fails only if `/proc` cannot be read.
+///
+/// Processes are found by scanning `/proc`, so this is inherently racy:
+/// processes which join the group or are started during the scan may be
+/// missed, and a process may exit (and its ID be reused) between being found
+/// and having its limits set.
+pub fn set_group_resource_limit(
+    pgid: libc::pid_t,
+    resource: Resource,
+    limits: ResourceLimits,
+) -> std::io::Result<Vec<libc::pid_t>> {
+    let mut pids = Vec::new();
+    for entry in std::fs::read_dir("/proc")? {
+        let pid: libc::pid_t = match entry?
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            Some(pid) => pid,
+            None => continue,
+        };
+        if unsafe { libc::getpgid(pid) } == pgid && prlimit_set(pid, resource, limits).is_ok() {
+            pids.push(pid);
+        }
+    }
+    pids.sort_unstable();
+    Ok(pids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_only_for_the_current_process() {
+        let parent = unsafe { libc::getppid() };
+        let fallback = || Ok(1);
+        assert_eq!(
+            with_fallback(0, Err(PrLimitError::Unsupported), fallback),
+            Ok(1)
+        );
+        assert_eq!(
+            with_fallback(parent, Err(PrLimitError::Unsupported), fallback),
+            Err(PrLimitError::Unsupported)
+        );
+        assert_eq!(
+            with_fallback(0, Err(PrLimitError::Permission), fallback),
+            Err(PrLimitError::Permission)
+        );
+        assert_eq!(with_fallback(0, Ok(2), fallback), Ok(2));
+        // A simulated ENOSYS for the current process reads the limits with
+        // getrlimit instead.
+        assert_eq!(
+            with_fallback(0, Err(PrLimitError::Unsupported), || {
+                prlimit_fallback(Resource::OpenFiles, None)
+            }),
+            get_resource_limit(Resource::OpenFiles).map_err(PrLimitError::from)
+        );
+    }
+
+    // prlimit reads the real limits, so with the mock the limits set would
+    // not match those read.
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn inheriting_from_self_changes_nothing() {
+        let before = crate::get_all_resource_limits();
+        assert_eq!(inherit_limits_from(0), Ok(()));
+        assert_eq!(crate::get_all_resource_limits(), before);
+    }
+
+    #[test]
+    fn own_group_includes_itself() {
+        // A new group holds only this process, and leaving the runner's group
+        // must not affect other tests.
+        if !crate::in_isolated_process("prlimit::tests::own_group_includes_itself") {
+            return;
+        }
+        assert_eq!(unsafe { libc::setpgid(0, 0) }, 0);
+        let pid = unsafe { libc::getpid() };
+        let limits = prlimit_get(0, Resource::OpenFiles).unwrap();
+        assert_eq!(
+            set_group_resource_limit(pid, Resource::OpenFiles, limits).unwrap(),
+            [pid]
+        );
+    }
+
+    #[test]
+    fn reads_the_limits_of_a_child() {
+        let current = prlimit_get(0, Resource::OpenFiles).unwrap();
+        let lowered = ResourceLimits {
+            soft_limit: ResourceLimit::Value(64),
+            hard_limit: current.hard_limit,
+        };
+        let mut child = crate::spawn_with_limits(
+            std::process::Command::new("sleep").arg("10"),
+            &[(Resource::OpenFiles, lowered)],
+        )
+        .unwrap();
+        let limits = child_limits(child.id() as libc::pid_t);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(limits.contains(&(Resource::OpenFiles, lowered)));
+    }
+
+    #[test]
+    fn rttime_limits_include_the_main_thread() {
+        let pid = unsafe { libc::getpid() };
+        let limits = thread_rttime_limits().unwrap();
+        assert!(limits.iter().any(|&(tid, _)| tid == pid));
+    }
+
+    #[cfg(not(feature = "mock"))]
+    #[test]
+    fn adopting_from_self_changes_nothing() {
+        let before = crate::get_all_resource_limits();
+        assert_eq!(adopt_limits_capped(0), Ok(()));
+        assert_eq!(crate::get_all_resource_limits(), before);
+    }
+
+    #[test]
+    fn pid_zero_is_the_current_process() {
+        let pid = unsafe { libc::getpid() };
+        let limits = prlimit_get(0, Resource::OpenFiles).unwrap();
+        assert_eq!(prlimit_get(pid, Resource::OpenFiles), Ok(limits));
+        assert_eq!(prlimit_set(0, Resource::OpenFiles, limits), Ok(()));
+        assert_eq!(prlimit_get(0, Resource::OpenFiles), Ok(limits));
+    }
+}
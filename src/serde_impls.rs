@@ -0,0 +1,122 @@
+//! Serde support, enabled with the `serde` feature.
+//!
+//! A [`ResourceLimit`] is represented as a number, or one of the strings
+//! `"unlimited"` or `"unknown"`. A [`Resource`] is represented by its name,
+//! e.g. `"nofile"`.
+
+use crate::{rlim_to_u64, Resource, ResourceLimit, ResourceLimits};
+use serde::{Deserialize, Serialize};
+
+impl Serialize for ResourceLimit {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            ResourceLimit::Value(n) => serializer.serialize_u64(rlim_to_u64(n)),
+            ResourceLimit::Infinity | ResourceLimit::Unknown => serializer.collect_str(self),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ResourceLimit {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = ResourceLimit;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a number, \"unlimited\" or \"unknown\"")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, value: u64) -> Result<Self::Value, E> {
+                libc::rlim_t::try_from(value)
+                    .map(ResourceLimit::Value)
+                    .map_err(|_| E::invalid_value(serde::de::Unexpected::Unsigned(value), &self))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, value: i64) -> Result<Self::Value, E> {
+                libc::rlim_t::try_from(value)
+                    .map(ResourceLimit::Value)
+                    .map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(value), &self))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E> {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl Serialize for Resource {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Resource {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A flat wire representation of [`ResourceLimits`] for APIs which prefer
+/// plain numbers, e.g. `{ "soft": 1024, "hard": null }`, where `null` means
+/// unlimited. Unknown limits are also written as `null`, so they read back as
+/// unlimited (see [`ResourceLimits::as_options`]).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FlatLimits(pub ResourceLimits);
+
+#[derive(Serialize, Deserialize)]
+struct FlatRepr {
+    soft: Option<libc::rlim_t>,
+    hard: Option<libc::rlim_t>,
+}
+
+impl From<ResourceLimits> for FlatLimits {
+    fn from(limits: ResourceLimits) -> Self {
+        FlatLimits(limits)
+    }
+}
+
+impl From<FlatLimits> for ResourceLimits {
+    fn from(flat: FlatLimits) -> Self {
+        flat.0
+    }
+}
+
+impl Serialize for FlatLimits {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (soft, hard) = self.0.as_options();
+        FlatRepr { soft, hard }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FlatLimits {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let FlatRepr { soft, hard } = FlatRepr::deserialize(deserializer)?;
+        Ok(FlatLimits(ResourceLimits::from_options((soft, hard))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_limits_use_null_for_unlimited() {
+        let flat = FlatLimits(ResourceLimits::from_options((Some(1024), None)));
+        let json = serde_json::to_string(&flat).unwrap();
+        assert_eq!(json, r#"{"soft":1024,"hard":null}"#);
+        assert_eq!(serde_json::from_str::<FlatLimits>(&json).unwrap(), flat);
+        let unknown = FlatLimits(ResourceLimits {
+            soft_limit: ResourceLimit::Unknown,
+            hard_limit: ResourceLimit::Value(10),
+        });
+        assert_eq!(
+            serde_json::to_string(&unknown).unwrap(),
+            r#"{"soft":null,"hard":10}"#
+        );
+    }
+}
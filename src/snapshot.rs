@@ -0,0 +1,346 @@
+use crate::{
+    get_resource_limit, rlim_to_u64, GetRLimitError, Resource, ResourceLimit, ResourceLimits,
+    ALL_RESOURCES,
+};
+
+/// The version written by [`Snapshot::to_bytes`].
+const FORMAT_VERSION: u8 = 1;
+
+// Tags identifying the kind of each limit written by [`Snapshot::to_bytes`].
+const TAG_VALUE: u8 = 0;
+const TAG_INFINITY: u8 = 1;
+const TAG_UNKNOWN: u8 = 2;
+
+/// A saved set of limits, for example captured from the current process with
+/// [`Snapshot::capture`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Snapshot {
+    limits: Vec<(Resource, ResourceLimits)>,
+}
+
+/// An error value returned from the failure of [`Snapshot::from_bytes`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum DecodeError {
+    /// The buffer ended in the middle of the snapshot.
+    Truncated,
+    /// The snapshot was written in a version of the format which is not
+    /// understood.
+    UnsupportedVersion(u8),
+    /// A resource was not recognised.
+    InvalidResource(u8),
+    /// A limit was neither a value, unlimited, nor unknown.
+    InvalidLimit(u8),
+    /// A limit value is too large for this platform's `rlim_t`, e.g. when a
+    /// snapshot from a 64-bit system is decoded on a 32-bit one.
+    ValueOutOfRange(u64),
+    /// There were bytes left over after the snapshot.
+    TrailingBytes,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Truncated => write!(f, "snapshot is truncated"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version: {}", version)
+            }
+            Self::InvalidResource(key) => write!(f, "invalid resource in snapshot: {}", key),
+            Self::InvalidLimit(tag) => write!(f, "invalid limit in snapshot: {}", tag),
+            Self::ValueOutOfRange(value) => {
+                write!(f, "limit value in snapshot is out of range: {}", value)
+            }
+            Self::TrailingBytes => write!(f, "trailing bytes after snapshot"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl Snapshot {
+    /// Create a snapshot from a list of limits.
+    pub fn new(limits: Vec<(Resource, ResourceLimits)>) -> Snapshot {
+        Snapshot { limits }
+    }
+
+    /// Capture the current limits of every resource. Resources whose limits
+    /// cannot be read are omitted.
+    pub fn capture() -> Snapshot {
+        Snapshot::new(
+            Resource::all()
+                .iter()
+                .filter_map(|&resource| {
+                    get_resource_limit(resource)
+                        .ok()
+                        .map(|limits| (resource, limits))
+                })
+                .collect(),
+        )
+    }
+
+    /// The limits in the snapshot.
+    pub fn limits(&self) -> &[(Resource, ResourceLimits)] {
+        &self.limits
+    }
+
+    /// The limits of a particular resource, if the snapshot has them.
+    pub fn get(&self, resource: Resource) -> Option<ResourceLimits> {
+        self.limits
+            .iter()
+            .find(|(r, _)| *r == resource)
+            .map(|&(_, limits)| limits)
+    }
+
+    /// Combine two snapshots, taking the more restrictive limits of each
+    /// resource (see [`ResourceLimits::tighten`]). Resources in only one of the
+    /// snapshots are included unchanged.
+    pub fn intersect(&self, other: &Snapshot) -> Snapshot {
+        let mut limits: Vec<(Resource, ResourceLimits)> = self
+            .limits
+            .iter()
+            .map(|&(resource, limits)| match other.get(resource) {
+                Some(other) => (resource, limits.tighten(other)),
+                None => (resource, limits),
+            })
+            .collect();
+        limits.extend(
+            other
+                .limits
+                .iter()
+                .filter(|(resource, _)| self.get(*resource).is_none()),
+        );
+        Snapshot::new(limits)
+    }
+
+    /// Compare the snapshot against the live limits, returning the resource,
+    /// the limits in the snapshot, and the current limits of each resource
+    /// which has changed. Fails if the live limits of any resource in the
+    /// snapshot cannot be read.
+    pub fn changed_since(
+        &self,
+    ) -> Result<Vec<(Resource, ResourceLimits, ResourceLimits)>, GetRLimitError> {
+        let mut changed = Vec::new();
+        for &(resource, saved) in &self.limits {
+            let current = get_resource_limit(resource)?;
+            if current != saved {
+                changed.push((resource, saved, current));
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Encode the snapshot in a compact binary format. The layout is a version
+    /// byte (currently 1), the number of entries as a little-endian `u32`,
+    /// then for each entry a resource byte followed by the soft and hard
+    /// limits.
+    ///
+    /// Resources are encoded with a fixed key rather than the platform's
+    /// resource code, so snapshots can be decoded on other platforms. Each
+    /// limit is a tag byte: 0 followed by the value as a little-endian `u64`,
+    /// 1 for unlimited, or 2 for unknown.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![FORMAT_VERSION];
+        bytes.extend_from_slice(&(self.limits.len() as u32).to_le_bytes());
+        for &(resource, limits) in &self.limits {
+            bytes.push(resource.canonical_key());
+            for limit in [limits.soft_limit, limits.hard_limit] {
+                match limit {
+                    ResourceLimit::Value(n) => {
+                        bytes.push(TAG_VALUE);
+                        bytes.extend_from_slice(&rlim_to_u64(n).to_le_bytes());
+                    }
+                    ResourceLimit::Infinity => bytes.push(TAG_INFINITY),
+                    ResourceLimit::Unknown => bytes.push(TAG_UNKNOWN),
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Decode a snapshot written by [`Snapshot::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, DecodeError> {
+        let mut reader = Reader { bytes };
+        match reader.byte()? {
+            FORMAT_VERSION => {}
+            version => return Err(DecodeError::UnsupportedVersion(version)),
+        }
+        let count = u32::from_le_bytes(reader.array()?);
+        let mut limits = Vec::new();
+        for _ in 0..count {
+            let key = reader.byte()?;
+            let resource = *ALL_RESOURCES
+                .get(key as usize)
+                .ok_or(DecodeError::InvalidResource(key))?;
+            let soft_limit = reader.limit()?;
+            let hard_limit = reader.limit()?;
+            limits.push((
+                resource,
+                ResourceLimits {
+                    soft_limit,
+                    hard_limit,
+                },
+            ));
+        }
+        if !reader.bytes.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(Snapshot::new(limits))
+    }
+}
+
+/// Reads the fields of an encoded [`Snapshot`] from the front of a buffer.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        if self.bytes.len() < N {
+            return Err(DecodeError::Truncated);
+        }
+        let (head, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        Ok(head.try_into().expect("split at N"))
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        self.array::<1>().map(|[byte]| byte)
+    }
+
+    fn limit(&mut self) -> Result<ResourceLimit, DecodeError> {
+        match self.byte()? {
+            TAG_VALUE => {
+                let value = u64::from_le_bytes(self.array()?);
+                libc::rlim_t::try_from(value)
+                    .map(ResourceLimit::Value)
+                    .map_err(|_| DecodeError::ValueOutOfRange(value))
+            }
+            TAG_INFINITY => Ok(ResourceLimit::unlimited()),
+            TAG_UNKNOWN => Ok(ResourceLimit::Unknown),
+            tag => Err(DecodeError::InvalidLimit(tag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot::new(vec![
+            (
+                Resource::OpenFiles,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Value(1024),
+                    hard_limit: ResourceLimit::Infinity,
+                },
+            ),
+            (
+                Resource::CoreFileSize,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Unknown,
+                    hard_limit: ResourceLimit::Value(0),
+                },
+            ),
+        ])
+    }
+
+    #[test]
+    fn encodes_documented_layout() {
+        let mut expected = vec![1, 2, 0, 0, 0];
+        expected.extend([4, 0]);
+        expected.extend(1024u64.to_le_bytes());
+        expected.push(1);
+        expected.extend([0, 2, 0]);
+        expected.extend(0u64.to_le_bytes());
+        assert_eq!(snapshot().to_bytes(), expected);
+    }
+
+    #[test]
+    fn round_trips() {
+        let snapshot = snapshot();
+        assert_eq!(Snapshot::from_bytes(&snapshot.to_bytes()), Ok(snapshot));
+        let empty = Snapshot::default();
+        assert_eq!(Snapshot::from_bytes(&empty.to_bytes()), Ok(empty));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let bytes = snapshot().to_bytes();
+        assert_eq!(Snapshot::from_bytes(&[]), Err(DecodeError::Truncated));
+        assert_eq!(
+            Snapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::Truncated)
+        );
+        assert_eq!(
+            Snapshot::from_bytes(&[2, 0, 0, 0, 0]),
+            Err(DecodeError::UnsupportedVersion(2))
+        );
+        assert_eq!(
+            Snapshot::from_bytes(&[1, 1, 0, 0, 0, 7, 1, 1]),
+            Err(DecodeError::InvalidResource(7))
+        );
+        assert_eq!(
+            Snapshot::from_bytes(&[1, 1, 0, 0, 0, 4, 3, 1]),
+            Err(DecodeError::InvalidLimit(3))
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            Snapshot::from_bytes(&trailing),
+            Err(DecodeError::TrailingBytes)
+        );
+    }
+
+    #[test]
+    fn intersect_tightens_shared_resources() {
+        let other = Snapshot::new(vec![
+            (
+                Resource::OpenFiles,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Value(2048),
+                    hard_limit: ResourceLimit::Value(4096),
+                },
+            ),
+            (
+                Resource::StackSize,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Value(8192),
+                    hard_limit: ResourceLimit::Infinity,
+                },
+            ),
+        ]);
+        let merged = snapshot().intersect(&other);
+        assert_eq!(
+            merged.limits(),
+            &[
+                (
+                    Resource::OpenFiles,
+                    ResourceLimits {
+                        soft_limit: ResourceLimit::Value(1024),
+                        hard_limit: ResourceLimit::Value(4096),
+                    }
+                ),
+                snapshot().limits()[1],
+                other.limits()[1],
+            ]
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn changed_since_reports_a_changed_limit() {
+        let before = crate::limits(1024, 4096);
+        let after = crate::limits(512, 4096);
+        crate::mock::set_table(&[
+            (Resource::OpenFiles, before),
+            (Resource::StackSize, crate::limits(8, 16)),
+        ]);
+        let snapshot = Snapshot::capture();
+        assert_eq!(snapshot.changed_since(), Ok(vec![]));
+        crate::set_resource_limit(Resource::OpenFiles, after).unwrap();
+        assert_eq!(
+            snapshot.changed_since(),
+            Ok(vec![(Resource::OpenFiles, before, after)])
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{get_resource_limit, rlim_to_u64, GetRLimitError, Resource, ResourceLimit};
+
+/// Query a system configuration value with sysconf.
+fn sysconf(name: libc::c_int) -> std::io::Result<u64> {
+    match unsafe { libc::sysconf(name) } {
+        -1 => Err(std::io::Error::last_os_error()),
+        value => Ok(value as u64),
+    }
+}
+
+/// Get the size of a memory page in bytes, as reported by
+/// sysconf(_SC_PAGESIZE). The value is cached after the first call.
+pub fn page_size() -> usize {
+    static PAGE_SIZE: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+    *PAGE_SIZE.get_or_init(|| {
+        sysconf(libc::_SC_PAGESIZE)
+            .ok()
+            .and_then(|size| usize::try_from(size).ok())
+            .expect("Invalid page size")
+    })
+}
+
+/// The total amount of physical memory in the system, in bytes.
+pub(crate) fn physical_memory_bytes() -> std::io::Result<u64> {
+    let pages = sysconf(libc::_SC_PHYS_PAGES)?;
+    Ok(pages.saturating_mul(page_size() as u64))
+}
+
+impl ResourceLimit {
+    /// Create a byte limit which is the given fraction of the total physical
+    /// memory of the system (e.g. `0.5` for half), for use with resources
+    /// such as [`Resource::TotalMemory`](crate::Resource::TotalMemory). The
+    /// fraction must be between 0 and 1.
+    ///
+    /// Fails with [`std::io::ErrorKind::InvalidInput`] if the resulting
+    /// number of bytes cannot be represented as a finite limit, which happens
+    /// on targets with a 32-bit `rlim_t` for 4 GiB or more.
+    pub fn from_memory_fraction(fraction: f64) -> std::io::Result<ResourceLimit> {
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "memory fraction must be between 0 and 1",
+            ));
+        }
+        let total = physical_memory_bytes()?;
+        // The fraction is at most 1, so this cannot saturate u64.
+        let bytes = (total as f64 * fraction) as u64;
+        match libc::rlim_t::try_from(bytes) {
+            Ok(bytes) if bytes != libc::RLIM_INFINITY => Ok(ResourceLimit::Value(bytes)),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "memory limit is too large for rlim_t",
+            )),
+        }
+    }
+}
+
+/// Get the number of file descriptors currently open in this process, for
+/// comparison against the [`Resource::OpenFiles`](crate::Resource::OpenFiles)
+/// soft limit. This counts the entries in `/proc/self/fd`.
+#[cfg(target_os = "linux")]
+pub fn open_file_count() -> std::io::Result<u64> {
+    let mut count: u64 = 0;
+    for entry in std::fs::read_dir("/proc/self/fd")? {
+        entry?;
+        count += 1;
+    }
+    // Reading the directory needs a descriptor of its own, which is listed too.
+    Ok(count.saturating_sub(1))
+}
+
+/// The kernel's ceiling on the number of file descriptors a process may have
+/// open, read from `/proc/sys/fs/nr_open`. Even a privileged process cannot
+/// raise the [`Resource::OpenFiles`] limit above this.
+#[cfg(target_os = "linux")]
+pub(crate) fn nr_open() -> std::io::Result<libc::rlim_t> {
+    std::fs::read_to_string("/proc/sys/fs/nr_open")?
+        .trim()
+        .parse()
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+/// Get the highest limit that could ever be set for a particular resource,
+/// given sufficient privileges. This is the hard limit, except that on Linux
+/// the [`Resource::OpenFiles`] limit can be raised up to
+/// `/proc/sys/fs/nr_open` (if that can be read).
+pub fn absolute_max(resource: Resource) -> Result<ResourceLimit, GetRLimitError> {
+    let hard_limit = get_resource_limit(resource)?.hard_limit;
+    #[cfg(target_os = "linux")]
+    if resource == Resource::OpenFiles {
+        if let (ResourceLimit::Value(hard), Ok(cap)) = (hard_limit, nr_open()) {
+            return Ok(ResourceLimit::Value(hard.max(cap)));
+        }
+    }
+    Ok(hard_limit)
+}
+
+/// Get the soft [`Resource::StackSize`] limit in pages of [`page_size`]
+/// bytes, rounded down. Returns `None` if the limit is unlimited or unknown.
+pub fn stack_limit_pages() -> Result<Option<u64>, GetRLimitError> {
+    Ok(match get_resource_limit(Resource::StackSize)?.soft_limit {
+        ResourceLimit::Value(bytes) => Some(rlim_to_u64(bytes) / page_size() as u64),
+        ResourceLimit::Infinity | ResourceLimit::Unknown => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_fraction_is_proportional() {
+        let total = physical_memory_bytes().unwrap();
+        assert_eq!(
+            ResourceLimit::from_memory_fraction(1.0)
+                .unwrap()
+                .try_into_u64(),
+            Ok(total)
+        );
+        assert_eq!(
+            ResourceLimit::from_memory_fraction(0.5)
+                .unwrap()
+                .try_into_u64(),
+            Ok(total / 2)
+        );
+        for fraction in [-0.5, 1.5, f64::NAN, f64::INFINITY] {
+            assert!(ResourceLimit::from_memory_fraction(fraction).is_err());
+        }
+    }
+
+    #[test]
+    fn page_size_is_a_power_of_two() {
+        assert!(page_size().is_power_of_two());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn counts_the_standard_streams() {
+        assert!(open_file_count().unwrap() >= 3);
+    }
+
+    #[cfg(all(feature = "mock", target_os = "linux"))]
+    #[test]
+    fn absolute_max_of_open_files_includes_nr_open() {
+        crate::mock::set_table(&[
+            (Resource::OpenFiles, crate::limits(1024, 4096)),
+            (Resource::StackSize, crate::limits(8, 16)),
+        ]);
+        assert_eq!(
+            absolute_max(Resource::OpenFiles),
+            Ok(ResourceLimit::Value(nr_open().unwrap().max(4096)))
+        );
+        assert_eq!(
+            absolute_max(Resource::StackSize),
+            Ok(ResourceLimit::Value(16))
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn stack_limit_is_counted_in_pages() {
+        let mib = 1024 * 1024;
+        crate::mock::set_table(&[(Resource::StackSize, crate::limits(8 * mib, 8 * mib))]);
+        assert_eq!(
+            stack_limit_pages(),
+            Ok(Some(8 * 1024 * 1024 / page_size() as u64))
+        );
+        let unlimited = crate::ResourceLimits::from_options((None, None));
+        crate::mock::set_table(&[(Resource::StackSize, unlimited)]);
+        assert_eq!(stack_limit_pages(), Ok(None));
+    }
+}
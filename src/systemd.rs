@@ -0,0 +1,113 @@
+use crate::{Resource, ResourceLimit, ResourceLimits, ResourceUnit};
+
+/// An error value returned from the failure of [`parse_systemd_limit`].
+#[derive(Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub struct ParseSystemdLimitError {
+    /// The `key=value` setting which was not recognised.
+    pub input: String,
+}
+
+impl std::fmt::Display for ParseSystemdLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid systemd limit: {}", self.input)
+    }
+}
+
+impl std::error::Error for ParseSystemdLimitError {}
+
+/// Parse a single limit value in the units used by setrlimit. Sizes may use
+/// the `K`, `M`, and `G` suffixes, as systemd allows.
+fn parse_value(resource: Resource, value: &str) -> Option<ResourceLimit> {
+    let limit = match resource.unit() {
+        ResourceUnit::Bytes => ResourceLimit::parse_bytes(value).ok()?,
+        ResourceUnit::Seconds | ResourceUnit::Count => value.parse().ok()?,
+    };
+    match limit {
+        ResourceLimit::Unknown => None,
+        limit => Some(limit),
+    }
+}
+
+/// Parse a resource limit setting from a systemd unit file, such as
+/// `LimitNOFILE` with the value `1024:65536`. The value is either
+/// `soft:hard`, or a single value used for both. `infinity` means no limit.
+///
+/// Time values for `LimitCPU` must be given in plain seconds; systemd's time
+/// span syntax (e.g. `1h`) is not supported.
+pub fn parse_systemd_limit(
+    key: &str,
+    value: &str,
+) -> Result<(Resource, ResourceLimits), ParseSystemdLimitError> {
+    let parse = || {
+        let resource: Resource = key.trim().strip_prefix("Limit")?.parse().ok()?;
+        let value = value.trim();
+        let (soft, hard) = value.split_once(':').unwrap_or((value, value));
+        Some((
+            resource,
+            ResourceLimits {
+                soft_limit: parse_value(resource, soft)?,
+                hard_limit: parse_value(resource, hard)?,
+            },
+        ))
+    };
+    parse().ok_or_else(|| ParseSystemdLimitError {
+        input: format!("{}={}", key, value),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_systemd_limits() {
+        assert_eq!(
+            parse_systemd_limit("LimitNOFILE", "1024:65536"),
+            Ok((
+                Resource::OpenFiles,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Value(1024),
+                    hard_limit: ResourceLimit::Value(65536),
+                }
+            ))
+        );
+        assert_eq!(
+            parse_systemd_limit("LimitSTACK", "8M"),
+            Ok((
+                Resource::StackSize,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Value(8 << 20),
+                    hard_limit: ResourceLimit::Value(8 << 20),
+                }
+            ))
+        );
+        assert_eq!(
+            parse_systemd_limit("LimitCORE", "0:infinity"),
+            Ok((
+                Resource::CoreFileSize,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Value(0),
+                    hard_limit: ResourceLimit::Infinity,
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        for (key, value) in [
+            ("NOFILE", "1024"),
+            ("LimitNPROC", "1024"),
+            ("LimitNOFILE", "lots"),
+            ("LimitNOFILE", "unknown"),
+            ("LimitCPU", "1h"),
+        ] {
+            assert_eq!(
+                parse_systemd_limit(key, value),
+                Err(ParseSystemdLimitError {
+                    input: format!("{}={}", key, value)
+                })
+            );
+        }
+    }
+}
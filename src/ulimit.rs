@@ -0,0 +1,253 @@
+use crate::{limit_from_u64, Resource, ResourceLimit, ResourceLimits, ResourceUnit};
+
+/// Map a ulimit option letter to the resource it controls.
+fn resource_from_flag(flag: char) -> Option<Resource> {
+    Resource::all()
+        .iter()
+        .find(|resource| resource.ulimit_flag().chars().nth(1) == Some(flag))
+        .copied()
+}
+
+/// Map a resource name as printed by dash's `ulimit -a` to its option letter.
+fn flag_from_dash_name(name: &str) -> Option<char> {
+    match name {
+        "coredump" => Some('c'),
+        "time" => Some('t'),
+        "data" => Some('d'),
+        "file" => Some('f'),
+        "nofiles" => Some('n'),
+        "stack" => Some('s'),
+        "vmemory" => Some('v'),
+        _ => None,
+    }
+}
+
+/// Parse a value printed by ulimit, scaling it from the shell's units to the
+/// units used by setrlimit.
+fn parse_value(value: &str, scale: u64) -> Option<ResourceLimit> {
+    match value {
+        "unlimited" => Some(ResourceLimit::unlimited()),
+        n => n
+            .parse::<u64>()
+            .ok()
+            .map(|n| limit_from_u64(n.saturating_mul(scale))),
+    }
+}
+
+/// Parse a bash line such as `open files                      (-n) 1024`.
+fn parse_bash_line(line: &str) -> Option<(char, u64, &str)> {
+    let open = line.rfind('(')?;
+    let close = open + line[open..].find(')')?;
+    let inner = &line[open + 1..close];
+    let (unit, flag) = match inner.rsplit_once(',') {
+        Some((unit, flag)) => (unit.trim(), flag.trim()),
+        None => ("", inner.trim()),
+    };
+    let flag = flag.strip_prefix('-')?;
+    let mut chars = flag.chars();
+    let flag = chars.next()?;
+    if chars.next().is_some() {
+        return None;
+    }
+    // Bash reports sizes in 1024-byte increments, even for "blocks" outside
+    // of POSIX mode.
+    let scale = match unit {
+        "kbytes" | "blocks" => 1024,
+        "512 bytes" => 512,
+        _ => 1,
+    };
+    Some((flag, scale, line[close + 1..].trim()))
+}
+
+/// Parse a dash line such as `nofiles              1024`, or the newer
+/// `-n: file descriptors           1024` form.
+fn parse_dash_line(line: &str) -> Option<(char, u64, &str)> {
+    let (label, value) = line.trim().rsplit_once(char::is_whitespace)?;
+    let label = label.trim();
+    if let Some(rest) = label.strip_prefix('-') {
+        let mut chars = rest.chars();
+        let flag = chars.next()?;
+        let rest = chars.as_str().strip_prefix(':')?;
+        let scale = if rest.contains("(blocks)") {
+            512
+        } else if rest.contains("(kbytes)") {
+            1024
+        } else {
+            1
+        };
+        return Some((flag, scale, value));
+    }
+    let (name, scale) = match label.split_once('(') {
+        Some((name, "blocks)")) => (name, 512),
+        Some((name, "kbytes)")) => (name, 1024),
+        Some((name, _)) => (name, 1),
+        None => (label, 1),
+    };
+    Some((flag_from_dash_name(name)?, scale, value))
+}
+
+/// Parse the output of `ulimit -a` as printed by bash or dash into the limits
+/// it describes. Values are converted to the units used by setrlimit (bytes
+/// for sizes, seconds for CPU time). Lines for resources this crate does not
+/// know about, or that cannot be parsed, are skipped.
+///
+/// The shell only prints one limit (the soft limit unless `-H` was given), so
+/// each resource is paired with a single [`ResourceLimit`].
+pub fn parse_ulimit_output(text: &str) -> Vec<(Resource, ResourceLimit)> {
+    text.lines()
+        .filter_map(|line| {
+            let (flag, scale, value) = if line.contains(", -") || line.contains("(-") {
+                parse_bash_line(line)
+            } else {
+                parse_dash_line(line)
+            }?;
+            let resource = resource_from_flag(flag)?;
+            Some((resource, parse_value(value, scale)?))
+        })
+        .collect()
+}
+
+/// Format a limit in the units bash's ulimit uses for `resource`, rounding
+/// sizes up to whole kilobytes so that the limit is never made tighter.
+/// Returns `None` for an unknown limit.
+fn format_value(resource: Resource, limit: ResourceLimit) -> Option<String> {
+    match limit {
+        ResourceLimit::Infinity => Some("unlimited".to_string()),
+        ResourceLimit::Unknown => None,
+        ResourceLimit::Value(n) => Some(match resource.unit() {
+            ResourceUnit::Bytes => n.div_ceil(1024).to_string(),
+            ResourceUnit::Seconds | ResourceUnit::Count => n.to_string(),
+        }),
+    }
+}
+
+/// Generate a shell script of bash `ulimit` commands which apply the given
+/// limits, e.g. `ulimit -S -n 1024`. Sizes are written in kilobytes, as bash
+/// expects outside of POSIX mode, rounded up if they are not a whole number of
+/// kilobytes. Limits which are unknown are skipped.
+///
+/// Each soft limit is set before the hard limit, so the script only works for
+/// lowering limits: when replaying a soft limit above the current hard limit
+/// the `-S` command fails before the `-H` command raises the hard limit.
+pub fn to_ulimit_script(limits: &[(Resource, ResourceLimits)]) -> String {
+    let mut script = String::new();
+    for &(resource, limits) in limits {
+        for (option, limit) in [("-S", limits.soft_limit), ("-H", limits.hard_limit)] {
+            if let Some(value) = format_value(resource, limit) {
+                script.push_str(&format!(
+                    "ulimit {} {} {}\n",
+                    option,
+                    resource.ulimit_flag(),
+                    value
+                ));
+            }
+        }
+    }
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bash_output() {
+        let text = "\
+real-time non-blocking time  (microseconds, -R) unlimited
+core file size              (blocks, -c) 0
+data seg size               (kbytes, -d) unlimited
+scheduling priority                 (-e) 0
+file size                   (blocks, -f) unlimited
+pending signals                     (-i) 63362
+max locked memory           (kbytes, -l) 8192
+max memory size             (kbytes, -m) unlimited
+open files                          (-n) 1024
+pipe size                (512 bytes, -p) 8
+POSIX message queues         (bytes, -q) 819200
+real-time priority                  (-r) 0
+stack size                  (kbytes, -s) 8192
+cpu time                   (seconds, -t) 60
+max user processes                  (-u) 63362
+virtual memory              (kbytes, -v) unlimited
+file locks                          (-x) unlimited
+";
+        assert_eq!(
+            parse_ulimit_output(text),
+            vec![
+                (Resource::CoreFileSize, ResourceLimit::Value(0)),
+                (Resource::DataSize, ResourceLimit::Infinity),
+                (Resource::FileSize, ResourceLimit::Infinity),
+                (Resource::OpenFiles, ResourceLimit::Value(1024)),
+                (Resource::StackSize, ResourceLimit::Value(8192 * 1024)),
+                (Resource::CPUTime, ResourceLimit::Value(60)),
+                (Resource::TotalMemory, ResourceLimit::Infinity),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_dash_output() {
+        let old = "time(seconds)        unlimited\nnofiles              1024\nstack(kbytes)        8192\n";
+        let new = "-t: time(seconds)           unlimited\n-n: file descriptors        1024\n-s: stack size (kbytes)     8192\n";
+        for text in [old, new] {
+            assert_eq!(
+                parse_ulimit_output(text),
+                vec![
+                    (Resource::CPUTime, ResourceLimit::Infinity),
+                    (Resource::OpenFiles, ResourceLimit::Value(1024)),
+                    (Resource::StackSize, ResourceLimit::Value(8192 * 1024)),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn writes_ulimit_script() {
+        let limits = [
+            (
+                Resource::OpenFiles,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Value(1024),
+                    hard_limit: ResourceLimit::Value(4096),
+                },
+            ),
+            (
+                Resource::StackSize,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Value(8 * 1024 * 1024),
+                    hard_limit: ResourceLimit::Infinity,
+                },
+            ),
+            (
+                Resource::CPUTime,
+                ResourceLimits {
+                    soft_limit: ResourceLimit::Unknown,
+                    hard_limit: ResourceLimit::Value(60),
+                },
+            ),
+        ];
+        assert_eq!(
+            to_ulimit_script(&limits),
+            "ulimit -S -n 1024\n\
+             ulimit -H -n 4096\n\
+             ulimit -S -s 8192\n\
+             ulimit -H -s unlimited\n\
+             ulimit -H -t 60\n"
+        );
+    }
+
+    #[test]
+    fn rounds_sizes_up_to_whole_kilobytes() {
+        let limits = [(
+            Resource::FileSize,
+            ResourceLimits {
+                soft_limit: ResourceLimit::Value(1000),
+                hard_limit: ResourceLimit::Value(2048),
+            },
+        )];
+        assert_eq!(
+            to_ulimit_script(&limits),
+            "ulimit -S -f 1\nulimit -H -f 2\n"
+        );
+    }
+}
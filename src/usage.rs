@@ -0,0 +1,790 @@
+use crate::{
+    get_resource_limit, limit_from_u64, rlim_to_u64, set_resource_limit, GetRLimitError, Resource,
+    ResourceLimit, ResourceLimits, ResourceUnit, SetRLimitError,
+};
+
+/// Call getrusage for the given target (e.g. `libc::RUSAGE_SELF`).
+pub(crate) fn rusage(who: libc::c_int) -> std::io::Result<libc::rusage> {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    unsafe {
+        match libc::getrusage(who, &mut usage) {
+            0 => Ok(usage),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }
+}
+
+/// Whose resource usage to get with [`get_resource_usage`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Ord, PartialOrd, Hash)]
+pub enum RUsageWho {
+    /// The current process, summed over all of its threads (RUSAGE_SELF).
+    Process,
+    /// All children of the current process which have terminated and been
+    /// waited for (RUSAGE_CHILDREN).
+    Children,
+    /// The calling thread only (RUSAGE_THREAD).
+    #[cfg(target_os = "linux")]
+    Thread,
+}
+
+impl From<RUsageWho> for libc::c_int {
+    fn from(who: RUsageWho) -> Self {
+        match who {
+            RUsageWho::Process => libc::RUSAGE_SELF,
+            RUsageWho::Children => libc::RUSAGE_CHILDREN,
+            #[cfg(target_os = "linux")]
+            RUsageWho::Thread => libc::RUSAGE_THREAD,
+        }
+    }
+}
+
+/// Resource usage as reported by getrusage.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceUsage {
+    pub user_time: std::time::Duration,
+    pub system_time: std::time::Duration,
+    /// The peak resident set size in bytes. On Linux this is always for the
+    /// whole process, even with [`RUsageWho::Thread`].
+    pub max_rss_bytes: u64,
+    pub voluntary_context_switches: u64,
+    pub involuntary_context_switches: u64,
+}
+
+/// Get the resource usage of the current process, its children, or the
+/// calling thread.
+pub fn get_resource_usage(who: RUsageWho) -> std::io::Result<ResourceUsage> {
+    let usage = rusage(who.into())?;
+    Ok(ResourceUsage {
+        user_time: timeval_duration(&usage.ru_utime),
+        system_time: timeval_duration(&usage.ru_stime),
+        max_rss_bytes: maxrss_bytes(&usage),
+        voluntary_context_switches: u64::try_from(usage.ru_nvcsw).unwrap_or(0),
+        involuntary_context_switches: u64::try_from(usage.ru_nivcsw).unwrap_or(0),
+    })
+}
+
+/// Get the number of `(voluntary, involuntary)` context switches of the
+/// current process. Voluntary switches happen when the process blocks, e.g. on
+/// I/O, and involuntary ones when the scheduler preempts it.
+pub fn context_switches() -> std::io::Result<(u64, u64)> {
+    let usage = get_resource_usage(RUsageWho::Process)?;
+    Ok((
+        usage.voluntary_context_switches,
+        usage.involuntary_context_switches,
+    ))
+}
+
+/// Convert the `ru_maxrss` field to bytes. Most platforms report this value in
+/// kilobytes, but Apple platforms report it in bytes.
+pub(crate) fn maxrss_bytes(usage: &libc::rusage) -> u64 {
+    if cfg!(any(target_os = "macos", target_os = "ios")) {
+        usage.ru_maxrss as u64
+    } else {
+        (usage.ru_maxrss as u64).saturating_mul(1024)
+    }
+}
+
+/// Convert a `timeval` to a [`Duration`](std::time::Duration), treating
+/// negative fields as zero.
+fn timeval_duration(tv: &libc::timeval) -> std::time::Duration {
+    let secs = u64::try_from(tv.tv_sec).unwrap_or(0);
+    let micros = u64::try_from(tv.tv_usec).unwrap_or(0);
+    std::time::Duration::from_secs(secs) + std::time::Duration::from_micros(micros)
+}
+
+/// The total CPU time (user and system) consumed, in whole seconds.
+pub(crate) fn cpu_seconds(usage: &libc::rusage) -> u64 {
+    (timeval_duration(&usage.ru_utime) + timeval_duration(&usage.ru_stime)).as_secs()
+}
+
+/// The usage of a resource as reported by getrusage, in the same units as
+/// its limit. Returns `None` for resources getrusage does not report on.
+pub(crate) fn observed_usage(resource: Resource, usage: &libc::rusage) -> Option<u64> {
+    match resource {
+        Resource::CPUTime => Some(cpu_seconds(usage)),
+        Resource::TotalMemory => Some(maxrss_bytes(usage)),
+        Resource::CoreFileSize
+        | Resource::DataSize
+        | Resource::FileSize
+        | Resource::OpenFiles
+        | Resource::StackSize => None,
+    }
+}
+
+/// Get the resources whose usage getrusage reports on, which are
+/// [`Resource::CPUTime`] (CPU time consumed) and [`Resource::TotalMemory`]
+/// (approximated by the peak resident set size). These are the resources for
+/// which [`ResourceStatus::used`] is available.
+pub fn observable_resources() -> Vec<Resource> {
+    let usage: libc::rusage = unsafe { std::mem::zeroed() };
+    Resource::all()
+        .iter()
+        .copied()
+        .filter(|&resource| observed_usage(resource, &usage).is_some())
+        .collect()
+}
+
+/// Get the lowest soft limit that can be set for a particular resource without
+/// falling below what the current process is already using.
+///
+/// For [`Resource::CPUTime`] this is the CPU time consumed so far (in seconds)
+/// and for [`Resource::TotalMemory`] it is the peak resident set size (in
+/// bytes). Resources without observable usage return `ResourceLimit::Value(0)`.
+///
+/// If getrusage fails, the error is reported as [`GetRLimitError::Invalid`],
+/// since the return type has no room for it. That variant's
+/// [`raw_os_error`](GetRLimitError::raw_os_error) is then `EINVAL`, even
+/// though getrlimit was never called and getrusage may have failed
+/// differently.
+pub fn min_settable_soft(resource: Resource) -> Result<ResourceLimit, GetRLimitError> {
+    let usage = rusage(libc::RUSAGE_SELF).map_err(|_| GetRLimitError::Invalid)?;
+    Ok(limit_from_u64(
+        observed_usage(resource, &usage).unwrap_or(0),
+    ))
+}
+
+/// The limits of a resource along with the current usage of that resource.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceStatus {
+    pub resource: Resource,
+    pub limits: Result<ResourceLimits, GetRLimitError>,
+    /// The current usage, in the same units as the limits. This is `None` for
+    /// resources which getrusage does not report on.
+    pub used: Option<u64>,
+}
+
+impl ResourceStatus {
+    /// The current usage as a fraction of the soft limit, e.g. `0.5` when half
+    /// of the limit is used. Returns `None` if the usage is not observable, or
+    /// the soft limit is unlimited, unknown, zero, or could not be read.
+    pub fn usage_ratio(&self) -> Option<f64> {
+        match (self.used, self.limits.ok()?.soft_limit) {
+            (Some(used), ResourceLimit::Value(soft)) if soft > 0 => Some(used as f64 / soft as f64),
+            _ => None,
+        }
+    }
+}
+
+/// Get the limits and current usage of every resource.
+pub fn resource_status() -> Vec<ResourceStatus> {
+    let usage = rusage(libc::RUSAGE_SELF).ok();
+    Resource::all()
+        .iter()
+        .map(|&resource| ResourceStatus {
+            resource,
+            limits: get_resource_limit(resource),
+            used: usage
+                .as_ref()
+                .and_then(|usage| observed_usage(resource, usage)),
+        })
+        .collect()
+}
+
+/// Everything needed to report on a resource for monitoring, as returned by
+/// [`resource_report`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceReport {
+    pub resource: Resource,
+    pub soft: ResourceLimit,
+    pub hard: ResourceLimit,
+    /// The current usage, or `None` if getrusage does not report on this
+    /// resource.
+    pub used: Option<u64>,
+    pub unit: ResourceUnit,
+}
+
+/// Get a report of the limits, usage, and unit of every resource. If the
+/// limits of a resource cannot be read they are reported as
+/// [`ResourceLimit::Unknown`].
+pub fn resource_report() -> Vec<ResourceReport> {
+    resource_status()
+        .into_iter()
+        .map(|status| {
+            let limits = status.limits.unwrap_or(ResourceLimits {
+                soft_limit: ResourceLimit::Unknown,
+                hard_limit: ResourceLimit::Unknown,
+            });
+            crate::instrumentation::record_soft_limit(status.resource, limits.soft_limit);
+            if let Some(used) = status.used {
+                crate::instrumentation::record_usage(status.resource, used);
+            }
+            ResourceReport {
+                resource: status.resource,
+                soft: limits.soft_limit,
+                hard: limits.hard_limit,
+                used: status.used,
+                unit: status.resource.unit(),
+            }
+        })
+        .collect()
+}
+
+/// Soft limits of the resources checked by [`check_limit_breaches`], cached
+/// so that polling only needs a single getrusage call.
+static SOFT_LIMIT_CACHE: std::sync::Mutex<Option<Vec<(Resource, ResourceLimit)>>> =
+    std::sync::Mutex::new(None);
+
+/// Discard the cached soft limits. This is called whenever the crate changes a
+/// limit of the current process.
+pub(crate) fn invalidate_soft_limit_cache() {
+    if let Ok(mut cache) = SOFT_LIMIT_CACHE.lock() {
+        *cache = None;
+    }
+}
+
+/// Get the resources whose current usage is at or over their soft limit.
+///
+/// This is intended to be polled: each call makes a single getrusage call and
+/// compares against soft limits cached from the first call. The cache is
+/// refreshed when limits are changed through this crate, but not when they
+/// are changed by other means. With the `mock` feature nothing is cached, as
+/// each thread's table may differ. Only resources with observable usage (see
+/// [`ResourceStatus::used`]) can be reported.
+pub fn check_limit_breaches() -> Vec<Resource> {
+    let usage = match rusage(libc::RUSAGE_SELF) {
+        Ok(usage) => usage,
+        Err(_) => return Vec::new(),
+    };
+    let load = || -> Vec<(Resource, ResourceLimit)> {
+        Resource::all()
+            .iter()
+            .filter(|&&resource| observed_usage(resource, &usage).is_some())
+            .filter_map(|&resource| {
+                get_resource_limit(resource)
+                    .ok()
+                    .map(|limits| (resource, limits.soft_limit))
+            })
+            .collect()
+    };
+    if cfg!(feature = "mock") {
+        return breached(&load(), &usage);
+    }
+    let mut cache = SOFT_LIMIT_CACHE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    breached(cache.get_or_insert_with(load), &usage)
+}
+
+/// The resources whose usage is at or over the given soft limits.
+fn breached(soft_limits: &[(Resource, ResourceLimit)], usage: &libc::rusage) -> Vec<Resource> {
+    soft_limits
+        .iter()
+        .filter_map(|&(resource, soft_limit)| match soft_limit {
+            ResourceLimit::Value(limit) => match observed_usage(resource, usage) {
+                Some(used) if used >= rlim_to_u64(limit) => Some(resource),
+                _ => None,
+            },
+            ResourceLimit::Infinity | ResourceLimit::Unknown => None,
+        })
+        .collect()
+}
+
+/// Get the peak resident set size of the current process, in bytes.
+pub fn peak_memory_bytes() -> std::io::Result<u64> {
+    rusage(libc::RUSAGE_SELF).map(|usage| maxrss_bytes(&usage))
+}
+
+/// Get the CPU time consumed by the current process, as a `(user, system)`
+/// pair.
+pub fn cpu_time() -> std::io::Result<(std::time::Duration, std::time::Duration)> {
+    rusage(libc::RUSAGE_SELF).map(|usage| {
+        (
+            timeval_duration(&usage.ru_utime),
+            timeval_duration(&usage.ru_stime),
+        )
+    })
+}
+
+/// Get how much CPU time the current process can consume before reaching its
+/// soft CPU limit, at which point it is sent SIGXCPU. Returns zero if the limit
+/// has already been reached, and `None` if the soft limit is unlimited or
+/// unknown.
+pub fn remaining_cpu_budget() -> std::io::Result<Option<std::time::Duration>> {
+    let limit = match get_resource_limit(Resource::CPUTime)?.soft_limit {
+        ResourceLimit::Value(secs) => std::time::Duration::from_secs(rlim_to_u64(secs)),
+        ResourceLimit::Infinity | ResourceLimit::Unknown => return Ok(None),
+    };
+    let (user, system) = cpu_time()?;
+    Ok(Some(limit.saturating_sub(user + system)))
+}
+
+/// An error value returned from the failure of [`set_resource_limit_detailed`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DetailedSetRLimitError {
+    /// [EINVAL] The limits were rejected for a resource with observable usage.
+    /// The current usage is included to show how far the request was from
+    /// what the process is already using.
+    Invalid {
+        requested: ResourceLimits,
+        current_usage: u64,
+    },
+    /// Any other failure, or EINVAL for a resource without observable usage.
+    Other(SetRLimitError),
+}
+
+impl std::fmt::Display for DetailedSetRLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Self::Invalid {
+                requested,
+                current_usage,
+            } => write!(
+                f,
+                "EINVAL (requested soft {} hard {}, current usage {})",
+                requested.soft_limit, requested.hard_limit, current_usage
+            ),
+            Self::Other(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for DetailedSetRLimitError {}
+
+/// Set the limit values for a particular resource as [`set_resource_limit`]
+/// does, but if they are rejected with EINVAL and the resource has observable
+/// usage, include the current usage in the error.
+///
+/// Note that Linux does not reject limits below the current usage (e.g. a
+/// lowered RLIMIT_AS only causes later allocations to fail), so there EINVAL is
+/// usually caused by a soft limit above the hard limit.
+pub fn set_resource_limit_detailed(
+    resource: Resource,
+    limits: ResourceLimits,
+) -> Result<(), DetailedSetRLimitError> {
+    match set_resource_limit(resource, limits) {
+        Err(SetRLimitError::Invalid) => {
+            let current_usage = rusage(libc::RUSAGE_SELF)
+                .ok()
+                .and_then(|usage| observed_usage(resource, &usage));
+            Err(match current_usage {
+                Some(current_usage) => DetailedSetRLimitError::Invalid {
+                    requested: limits,
+                    current_usage,
+                },
+                None => DetailedSetRLimitError::Other(SetRLimitError::Invalid),
+            })
+        }
+        result => result.map_err(DetailedSetRLimitError::Other),
+    }
+}
+
+/// Returns true if the peak resident set size of the current process exceeds
+/// `threshold` times the soft [`Resource::TotalMemory`] limit (e.g. `0.9` for
+/// 90%). Returns false if that limit is unlimited or unknown.
+///
+/// The peak resident set size is a cheap but conservative stand-in for the
+/// address space usage the limit applies to, which is usually larger.
+pub fn near_memory_limit(threshold: f64) -> std::io::Result<bool> {
+    let soft_limit = match get_resource_limit(Resource::TotalMemory)?.soft_limit {
+        ResourceLimit::Value(n) => n,
+        ResourceLimit::Infinity | ResourceLimit::Unknown => return Ok(false),
+    };
+    let used = peak_memory_bytes()?;
+    Ok(used as f64 > threshold * soft_limit as f64)
+}
+
+/// The current usage of a resource, preferring a live count where one is
+/// available over the values reported by getrusage.
+fn current_usage(resource: Resource) -> std::io::Result<Option<u64>> {
+    #[cfg(target_os = "linux")]
+    if resource == Resource::OpenFiles {
+        return crate::open_file_count().map(Some);
+    }
+    rusage(libc::RUSAGE_SELF).map(|usage| observed_usage(resource, &usage))
+}
+
+/// Wait until the usage of a resource is below its soft limit, checking every
+/// `poll_interval`. Returns true once it is, or false if `timeout` expires
+/// first. An unlimited or unknown soft limit counts as being under the limit.
+///
+/// This is only meaningful for resources whose usage can go down, which is
+/// [`Resource::OpenFiles`] on Linux (counted from `/proc/self/fd`). The usage
+/// getrusage reports for [`Resource::CPUTime`] and [`Resource::TotalMemory`]
+/// never decreases, so for those this only succeeds if the process is already
+/// under the limit or the limit is raised while waiting. For other resources
+/// an error of kind [`InvalidInput`](std::io::ErrorKind::InvalidInput) is
+/// returned.
+pub fn wait_under_limit(
+    resource: Resource,
+    poll_interval: std::time::Duration,
+    timeout: std::time::Duration,
+) -> std::io::Result<bool> {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        let under = match current_usage(resource) {
+            Ok(Some(used)) => match get_resource_limit(resource)?.soft_limit {
+                ResourceLimit::Value(limit) => used < rlim_to_u64(limit),
+                ResourceLimit::Infinity | ResourceLimit::Unknown => true,
+            },
+            Ok(None) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "usage of this resource cannot be observed",
+                ))
+            }
+            // Counting open files needs a descriptor, so at the limit the
+            // count itself fails.
+            Err(err) if err.raw_os_error() == Some(libc::EMFILE) => false,
+            Err(err) => return Err(err),
+        };
+        if under {
+            return Ok(true);
+        }
+        let now = std::time::Instant::now();
+        if now >= deadline {
+            return Ok(false);
+        }
+        std::thread::sleep(poll_interval.min(deadline - now));
+    }
+}
+
+/// The CPU and memory usage of the current process at a point in time, as
+/// returned by [`sample_usage`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceUsageSample {
+    /// When the sample was taken.
+    pub at: std::time::Instant,
+    /// The total CPU time (user and system) consumed so far.
+    pub cpu_time: std::time::Duration,
+    /// The peak resident set size so far, in bytes.
+    pub max_rss_bytes: u64,
+}
+
+impl ResourceUsageSample {
+    /// The average number of CPUs used between `earlier` and this sample, e.g.
+    /// 1.0 for one fully busy thread. Returns 0 if no time has elapsed.
+    pub fn cpu_rate_since(&self, earlier: &ResourceUsageSample) -> f64 {
+        let elapsed = self.at.saturating_duration_since(earlier.at).as_secs_f64();
+        if elapsed == 0.0 {
+            return 0.0;
+        }
+        self.cpu_time.saturating_sub(earlier.cpu_time).as_secs_f64() / elapsed
+    }
+}
+
+/// Take a sample of the CPU and memory usage of the current process. This
+/// makes a single getrusage call, so it is cheap enough to call periodically,
+/// for example from a monitoring task.
+pub fn sample_usage() -> std::io::Result<ResourceUsageSample> {
+    let usage = rusage(libc::RUSAGE_SELF)?;
+    Ok(ResourceUsageSample {
+        at: std::time::Instant::now(),
+        cpu_time: timeval_duration(&usage.ru_utime) + timeval_duration(&usage.ru_stime),
+        max_rss_bytes: maxrss_bytes(&usage),
+    })
+}
+
+/// Get the resources with observable usage and a finite soft limit, paired with
+/// their [`ResourceStatus::usage_ratio`], most pressured first.
+pub fn resources_by_pressure() -> Vec<(Resource, f64)> {
+    let mut pressure: Vec<(Resource, f64)> = resource_status()
+        .iter()
+        .filter_map(|status| Some((status.resource, status.usage_ratio()?)))
+        .collect();
+    pressure.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    pressure
+}
+
+/// Stops the watchdog thread started by [`enforce_with_watchdog`] when
+/// dropped.
+#[must_use = "the watchdog stops as soon as the handle is dropped"]
+#[derive(Debug)]
+pub struct WatchdogHandle {
+    stop: Option<std::sync::mpsc::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for WatchdogHandle {
+    fn drop(&mut self) {
+        // Disconnecting the channel wakes the thread, which then exits.
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Set the limit values for a particular resource, then start a thread which
+/// checks the usage of the resource every `interval` and calls `on_breach`
+/// with the resource and its usage each time the usage reaches the soft
+/// limit, having previously been below it. The thread runs until the returned
+/// handle is dropped.
+///
+/// Only resources with observable usage (see [`observable_resources`]) can be
+/// watched; for any other resource `on_breach` is never called. Note that
+/// reaching the soft [`Resource::CPUTime`] limit also sends SIGXCPU, which
+/// terminates the process unless it is handled or ignored, and that Linux then
+/// raises the soft limit by a second, so the breach may not be seen.
+pub fn enforce_with_watchdog<F>(
+    resource: Resource,
+    limits: ResourceLimits,
+    interval: std::time::Duration,
+    on_breach: F,
+) -> Result<WatchdogHandle, SetRLimitError>
+where
+    F: Fn(Resource, u64) + Send + 'static,
+{
+    set_resource_limit(resource, limits)?;
+    #[cfg(feature = "mock")]
+    let table = crate::mock::table();
+    let (stop, stopped) = std::sync::mpsc::channel::<()>();
+    let thread = std::thread::spawn(move || {
+        #[cfg(feature = "mock")]
+        crate::mock::set_table(&table);
+        let mut breached = false;
+        while let Err(std::sync::mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+            let used = rusage(libc::RUSAGE_SELF)
+                .ok()
+                .and_then(|usage| observed_usage(resource, &usage));
+            let soft_limit = get_resource_limit(resource).map(|limits| limits.soft_limit);
+            let over = match (used, soft_limit) {
+                (Some(used), Ok(ResourceLimit::Value(limit))) if used >= rlim_to_u64(limit) => {
+                    Some(used)
+                }
+                _ => None,
+            };
+            if let (Some(used), false) = (over, breached) {
+                on_breach(resource, used);
+            }
+            breached = over.is_some();
+        }
+    });
+    Ok(WatchdogHandle {
+        stop: Some(stop),
+        thread: Some(thread),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "mock")]
+    use crate::{limits, mock};
+
+    #[test]
+    fn min_settable_soft_is_within_the_soft_limit() {
+        #[cfg(feature = "mock")]
+        mock::set_table(&[(Resource::TotalMemory, limits(1 << 30, 1 << 30))]);
+        let soft_limit = get_resource_limit(Resource::TotalMemory)
+            .unwrap()
+            .soft_limit;
+        let min = min_settable_soft(Resource::TotalMemory).unwrap();
+        assert!(matches!(min, ResourceLimit::Value(n) if n > 0));
+        assert!(!min.exceeds(&soft_limit));
+        assert_eq!(
+            min_settable_soft(Resource::OpenFiles),
+            Ok(ResourceLimit::Value(0))
+        );
+    }
+
+    #[test]
+    fn resource_status_reports_observable_usage() {
+        let status = resource_status();
+        let resources: Vec<Resource> = status.iter().map(|status| status.resource).collect();
+        assert_eq!(resources, Resource::all());
+        let used = |resource: Resource| {
+            status
+                .iter()
+                .find(|status| status.resource == resource)
+                .unwrap()
+                .used
+        };
+        assert!(used(Resource::CPUTime).is_some());
+        assert_eq!(used(Resource::OpenFiles), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn reports_a_breached_cpu_limit() {
+        mock::set_table(&[
+            (
+                Resource::CPUTime,
+                ResourceLimits::from_options((Some(0), None)),
+            ),
+            (
+                Resource::TotalMemory,
+                ResourceLimits::from_options((None, None)),
+            ),
+        ]);
+        assert_eq!(check_limit_breaches(), vec![Resource::CPUTime]);
+    }
+
+    #[test]
+    fn peak_memory_is_positive() {
+        assert!(peak_memory_bytes().unwrap() > 0);
+    }
+
+    /// Keep the CPU busy for `duration`.
+    fn spin(duration: std::time::Duration) {
+        let start = std::time::Instant::now();
+        while start.elapsed() < duration {
+            std::hint::black_box(0);
+        }
+    }
+
+    #[test]
+    fn busy_loop_uses_user_time() {
+        spin(std::time::Duration::from_millis(50));
+        let (user, _) = cpu_time().unwrap();
+        assert!(user > std::time::Duration::ZERO);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn remaining_cpu_budget_subtracts_usage() {
+        mock::set_table(&[(
+            Resource::CPUTime,
+            ResourceLimits::from_options((Some(3600), None)),
+        )]);
+        let remaining = remaining_cpu_budget().unwrap().unwrap();
+        assert!(remaining < std::time::Duration::from_secs(3600));
+        mock::set_table(&[(
+            Resource::CPUTime,
+            ResourceLimits::from_options((None, None)),
+        )]);
+        assert_eq!(remaining_cpu_budget().unwrap(), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn detailed_errors_include_the_usage() {
+        mock::set_table(&[(Resource::TotalMemory, limits(1000, 1000))]);
+        let err = set_resource_limit_detailed(Resource::TotalMemory, limits(2000, 1000));
+        assert!(matches!(
+            err,
+            Err(DetailedSetRLimitError::Invalid { requested, current_usage })
+                if requested == limits(2000, 1000) && current_usage > 0
+        ));
+        assert_eq!(
+            set_resource_limit_detailed(Resource::OpenFiles, limits(1, 2)),
+            Err(DetailedSetRLimitError::Other(SetRLimitError::Invalid))
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn near_memory_limit_compares_with_the_soft_limit() {
+        let set_soft = |soft| {
+            mock::set_table(&[(
+                Resource::TotalMemory,
+                ResourceLimits::from_options((soft, None)),
+            )])
+        };
+        set_soft(Some(1));
+        assert!(near_memory_limit(0.9).unwrap());
+        set_soft(Some(libc::rlim_t::MAX - 1));
+        assert!(!near_memory_limit(0.9).unwrap());
+        set_soft(None);
+        assert!(!near_memory_limit(0.9).unwrap());
+    }
+
+    #[test]
+    fn resource_report_covers_every_resource() {
+        let report = resource_report();
+        let resources: Vec<Resource> = report.iter().map(|entry| entry.resource).collect();
+        assert_eq!(resources, Resource::all());
+        for entry in &report {
+            assert_eq!(entry.unit, entry.resource.unit());
+        }
+        let cpu = report
+            .iter()
+            .find(|entry| entry.resource == Resource::CPUTime)
+            .unwrap();
+        assert_eq!(cpu.unit, ResourceUnit::Seconds);
+        assert!(cpu.used.is_some());
+    }
+
+    #[test]
+    fn wait_under_limit_returns_at_once_when_under() {
+        #[cfg(feature = "mock")]
+        mock::set_table(&[(
+            Resource::OpenFiles,
+            ResourceLimits::from_options((None, None)),
+        )]);
+        let zero = std::time::Duration::ZERO;
+        #[cfg(target_os = "linux")]
+        assert!(wait_under_limit(Resource::OpenFiles, zero, zero).unwrap());
+        let err = wait_under_limit(Resource::CoreFileSize, zero, zero).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn thread_usage_is_part_of_process_usage() {
+        spin(std::time::Duration::from_millis(20));
+        let thread = get_resource_usage(RUsageWho::Thread).unwrap();
+        let process = get_resource_usage(RUsageWho::Process).unwrap();
+        assert!(thread.user_time > std::time::Duration::ZERO);
+        assert!(thread.user_time <= process.user_time);
+    }
+
+    #[test]
+    fn observable_resources_are_cpu_and_memory() {
+        assert_eq!(
+            observable_resources(),
+            vec![Resource::CPUTime, Resource::TotalMemory]
+        );
+    }
+
+    #[test]
+    fn busy_loop_has_a_positive_cpu_rate() {
+        let before = sample_usage().unwrap();
+        spin(std::time::Duration::from_millis(50));
+        let after = sample_usage().unwrap();
+        assert!(after.cpu_rate_since(&before) > 0.0);
+        assert_eq!(before.cpu_rate_since(&before), 0.0);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn most_pressured_resource_comes_first() {
+        mock::set_table(&[
+            (
+                Resource::CPUTime,
+                ResourceLimits::from_options((Some(1 << 30), None)),
+            ),
+            (
+                Resource::TotalMemory,
+                ResourceLimits::from_options((Some(1), None)),
+            ),
+        ]);
+        let pressure = resources_by_pressure();
+        let resources: Vec<Resource> = pressure.iter().map(|&(resource, _)| resource).collect();
+        assert_eq!(resources, [Resource::TotalMemory, Resource::CPUTime]);
+        assert!(pressure[0].1 > pressure[1].1);
+    }
+
+    #[test]
+    fn sleeping_is_a_voluntary_context_switch() {
+        let (before, _) = context_switches().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let (after, _) = context_switches().unwrap();
+        assert!(after > before);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn watchdog_reports_a_breach_until_dropped() {
+        mock::set_table(&[(
+            Resource::CPUTime,
+            ResourceLimits::from_options((None, None)),
+        )]);
+        let (sender, breaches) = std::sync::mpsc::channel();
+        let watchdog = enforce_with_watchdog(
+            Resource::CPUTime,
+            ResourceLimits::from_options((Some(0), None)),
+            std::time::Duration::from_millis(10),
+            move |resource, _| {
+                let _ = sender.send(resource);
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            breaches.recv_timeout(std::time::Duration::from_secs(5)),
+            Ok(Resource::CPUTime)
+        );
+        drop(watchdog);
+        // The callback, and so the sender, is dropped when the thread stops.
+        assert!(breaches.recv().is_err());
+    }
+}